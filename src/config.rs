@@ -0,0 +1,303 @@
+// src/config.rs
+
+use super::*;
+
+/// The config file's contents and where they came from: `--config`'s file, standard input for
+/// `--config -`, or the default path.
+fn read_config_file(cli: &Cli) -> Result<(String, PathBuf), Box<dyn Error + Send + Sync>> {
+    match cli.config.as_deref() {
+        Some(path) if path == Path::new("-") => {
+            let mut config_str = String::new();
+            std::io::stdin().read_to_string(&mut config_str).map_err(|e| format!("Could not read the config from standard input: {}.", e))?;
+            if config_str.trim().is_empty() {
+                return Err("`--config -` reads the config from standard input, but it was empty.".into());
+            }
+            Ok((config_str, PathBuf::from("<stdin>")))
+        }
+        Some(path) => {
+            Ok((read_config_at(path)?, path.to_path_buf()))
+        }
+        None => {
+            let config_path = Path::new(CONFIG_DIR).join(CONFIG_FILE);
+            create_config_template(&config_path)?;
+            Ok((read_config_at(&config_path)?, config_path))
+        }
+    }
+}
+
+/// Reads a config file, saying how to fix it when that's not allowed, as happens when it was
+/// created with sudo and is only readable by root.
+pub(crate) fn read_config_at(path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    fs::read_to_string(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => format!("There is no config file at '{}'.", path.display()),
+        std::io::ErrorKind::PermissionDenied => format!(
+            "The config file '{}' exists, but you don't have permission to read it (it was probably created with sudo). Try `sudo chown $USER {}`, or `sudo chmod a+r {}` to let everyone read it.",
+            path.display(), path.display(), path.display(),
+        ),
+        _ => format!("Could not read the config file '{}': {}.", path.display(), e),
+    }.into())
+}
+
+/// Writes a template to the default config path and exits, if there is no config there yet.
+fn create_config_template(config_path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let config_dir = Path::new(CONFIG_DIR);
+    if !config_path.exists() {
+        eprintln!("{} Config file not found at '{}'.", paint("Warning:", Role::Warning), config_path.display());
+        if !config_dir.exists() {
+            fs::create_dir_all(config_dir).map_err(|e| format!("Failed to create config directory at '{}': {}. Try `sudo mkdir -p {}`.", config_dir.display(), e, config_dir.display()))?;
+        }
+        let template = "[api]\ncookie = \"YourCookieHere\"\n";
+        fs::write(config_path, template).map_err(|e| format!("Failed to create config file at '{}': {}.", config_path.display(), e))?;
+        eprintln!("A template config has been created. Edit it with your cookie: `sudo nano {}`", config_path.display());
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The `[profile.NAME]` in use, from --profile or BSTT_PROFILE.
+pub(crate) fn active_profile(cli: &Cli) -> Option<String> {
+    cli.profile.clone().or_else(|| env::var("BSTT_PROFILE").ok())
+}
+
+/// Resolves the configuration from its layers: defaults, then the file, the selected profile,
+/// `BSTT_*` environment variables and finally command-line flags.
+pub(crate) fn load_or_create_config(cli: &Cli) -> Result<ResolvedConfig, Box<dyn Error + Send + Sync>> {
+    let (config_str, config_path) = read_config_file(cli)?;
+    // Reading the file on its own first reports syntax errors and duplicate keys with their line.
+    let mut file: toml::Table = toml::from_str(&config_str).map_err(|e| format!("Invalid config file '{}': {}", config_path.display(), e))?;
+    let profiles = file.remove("profile");
+    let mut merged = toml::Table::new();
+    let mut origins = BTreeMap::new();
+    merge_layer(&mut merged, file, "", &Origin::File(config_path.clone()), &mut origins)?;
+    if let Some(name) = active_profile(cli) {
+        let profile = profiles.as_ref().and_then(|profiles| profiles.get(&name)).and_then(toml::Value::as_table)
+            .ok_or_else(|| format!("There is no [profile.{}] in '{}'.", name, config_path.display()))?;
+        merge_layer(&mut merged, profile.clone(), "", &Origin::Profile(name), &mut origins)?;
+    }
+    let focus_path = focus_path(active_profile(cli).as_deref());
+    if let Some(unit) = load_focus(&focus_path) {
+        merge_layer(&mut merged, layer_at("display.focus", unit.into()), "", &Origin::File(focus_path), &mut origins)?;
+    }
+    for (var, layer) in env_layers() {
+        merge_layer(&mut merged, layer, "", &Origin::Env(var), &mut origins)?;
+    }
+    for (flag, layer) in cli_layers(cli) {
+        merge_layer(&mut merged, layer, "", &Origin::Cli(flag), &mut origins)?;
+    }
+    let config: Config = toml::Value::Table(merged).try_into().map_err(|e| format!("Invalid configuration: {}", e))?;
+
+    if config.api.cookie == "YourCookieHere" {
+        eprintln!("{} Your config at '{}' still contains the default value.", paint("Error:", Role::Problem).bold(), config_path.display());
+        eprintln!("Please replace 'YourCookieHere' with your actual cookie.");
+        std::process::exit(1);
+    }
+    timetable_source(&config.api)
+        .and_then(|source| request_headers(&config.api, source.as_ref()))
+        .map_err(|e| format!("{} (in '{}')", e, config_path.display()))?;
+    config.academic_calendar.validate().map_err(|e| format!("{} (in '{}')", e, config_path.display()))?;
+    if let Some((unit, colour)) = config.display.module_colors.iter().find(|(_, colour)| parse_cell_colour(colour).is_none()) {
+        return Err(format!("Unknown colour {:?} for {:?} in [display.module_colors] (in '{}'); use a name such as \"cyan\" or \"#rrggbb\".", colour, unit, config_path.display()).into());
+    }
+    if let Some(timezone) = &config.display.timezone {
+        let origin = origins.get("display.timezone").map(ToString::to_string).unwrap_or_default();
+        timezone.parse::<Tz>().map_err(|_| format!("Unknown timezone {:?} (from {}); use an IANA name such as America/New_York.", timezone, origin))?;
+    }
+    Ok(ResolvedConfig { config, origins, path: config_path })
+}
+
+
+/// Settings that have been renamed, as (section, old name, new name). Layers are read with the
+/// old names moved to the new, so that an old name in the file and a new one from a flag
+/// don't clash.
+const RENAMED_SETTINGS: &[(&str, &str, &str)] = &[
+    ("api", "window_before_days", "fetch_days_past"),
+    ("api", "window_after_days", "fetch_days_future"),
+];
+
+/// The config sections that `BSTT_<SECTION>_<KEY>` environment variables can set.
+const CONFIG_SECTIONS: &[&str] = &["api", "display", "log", "mini", "cache", "academic_calendar", "alarm", "serve", "filter", "changes", "telemetry"];
+
+/// Where a setting came from, when it isn't a default.
+#[derive(Debug, Clone)]
+pub(crate) enum Origin {
+    File(PathBuf),
+    Profile(String),
+    Env(String),
+    Cli(&'static str),
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Profile(name) => write!(f, "[profile.{}]", name),
+            Origin::Env(var) => write!(f, "${}", var),
+            Origin::Cli(flag) => write!(f, "{}", flag),
+        }
+    }
+}
+
+/// The effective configuration, with the origin of every setting not left at its default,
+/// keyed like "display.timezone".
+pub(crate) struct ResolvedConfig {
+    pub(crate) config: Config,
+    pub(crate) origins: BTreeMap<String, Origin>,
+    /// The file the config was read from, or "<stdin>".
+    pub(crate) path: PathBuf,
+}
+
+/// Lays `layer` over `merged`. Tables are merged key by key, so a profile can add one
+/// `unit_links` entry without repeating the rest; anything else replaces what was there.
+/// A layer setting both a renamed setting's old and new names is an error, as neither can be
+/// said to win.
+fn merge_layer(merged: &mut toml::Table, mut layer: toml::Table, prefix: &str, origin: &Origin, origins: &mut BTreeMap<String, Origin>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for (section, old, new) in RENAMED_SETTINGS.iter().filter(|(section, _, _)| *section == prefix) {
+        if let Some(value) = layer.remove(*old) {
+            if layer.contains_key(*new) {
+                return Err(format!("[{}] sets both {} and {}, which is its old name; keep only {} (in {}).", section, new, old, new, origin).into());
+            }
+            log_verbose(format!("[{}] {} is now called {}", section, old, new));
+            layer.insert(new.to_string(), value);
+        }
+    }
+    for (key, value) in layer {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            toml::Value::Table(table) => {
+                let existing = merged.entry(key).or_insert_with(|| toml::Table::new().into());
+                if !existing.is_table() {
+                    *existing = toml::Table::new().into();
+                }
+                merge_layer(existing.as_table_mut().unwrap(), table, &path, origin, origins)?;
+            }
+            value => {
+                origins.insert(path, origin.clone());
+                merged.insert(key, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A layer setting the single dotted `path`.
+fn layer_at(path: &str, value: toml::Value) -> toml::Table {
+    let mut keys = path.rsplit('.');
+    let mut layer = toml::Table::new();
+    layer.insert(keys.next().unwrap().to_string(), value);
+    for key in keys {
+        let mut outer = toml::Table::new();
+        outer.insert(key.to_string(), layer.into());
+        layer = outer;
+    }
+    layer
+}
+
+/// A layer per `BSTT_<SECTION>_<KEY>` variable, e.g. `BSTT_DISPLAY_TIMEZONE=Europe/London`.
+/// Values are read as TOML when they parse as such, so `BSTT_API_STRICT=true` is a boolean,
+/// and as plain strings otherwise.
+fn env_layers() -> Vec<(String, toml::Table)> {
+    let mut layers: Vec<(String, toml::Table)> = env::vars()
+        .filter_map(|(var, raw)| {
+            let name = var.strip_prefix("BSTT_")?.to_lowercase();
+            // Sections can contain underscores themselves, as in BSTT_ACADEMIC_CALENDAR_READING_WEEKS.
+            let (section, key) = CONFIG_SECTIONS.iter()
+                .find_map(|section| Some((*section, name.strip_prefix(section)?.strip_prefix('_')?.to_string())))?;
+            let value = toml::from_str::<toml::Table>(&format!("value = {}", raw)).ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(toml::Value::String(raw));
+            Some((var.clone(), layer_at(&format!("{}.{}", section, key), value)))
+        })
+        .collect();
+    layers.sort_by(|a, b| a.0.cmp(&b.0));
+    layers
+}
+
+/// The global flags that override settings, as layers.
+pub(crate) fn cli_layers(cli: &Cli) -> Vec<(&'static str, toml::Table)> {
+    let mut layers = Vec::new();
+    if let Some(days) = cli.window {
+        layers.push(("--window", layer_at("api.fetch_days_past", days.into())));
+        layers.push(("--window", layer_at("api.fetch_days_future", days.into())));
+    }
+    if cli.strict {
+        layers.push(("--strict", layer_at("api.strict", true.into())));
+    }
+    if cli.raw {
+        layers.push(("--raw", layer_at("api.raw_text", true.into())));
+    }
+    if let Some(format) = cli.cache_format {
+        let name = format.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+        layers.push(("--cache-format", layer_at("cache.format", name.into())));
+    }
+    if cli.refresh {
+        layers.push(("--refresh", layer_at("cache.ttl_minutes", 0.into())));
+    }
+    if let Some(density) = cli.density {
+        layers.push(("--density", layer_at("display.density", i64::from(density).into())));
+    }
+    if cli.twelve_hour {
+        layers.push(("--12h", layer_at("display.time_format_24h", false.into())));
+    }
+    if cli.seconds {
+        layers.push(("--seconds", layer_at("display.show_seconds", true.into())));
+    }
+    if cli.ascii {
+        layers.push(("--ascii", layer_at("display.ascii", true.into())));
+    }
+    if cli.unicode {
+        layers.push(("--unicode", layer_at("display.ascii", false.into())));
+    }
+    if cli.keep_groups {
+        layers.push(("--keep-groups", layer_at("display.strip_groups", false.into())));
+    }
+    if cli.strip_groups {
+        layers.push(("--strip-groups", layer_at("display.strip_groups", true.into())));
+    }
+    if cli.utc {
+        layers.push(("--utc", layer_at("display.timezone", "UTC".into())));
+    } else if let Some(timezone) = &cli.timezone {
+        layers.push(("--timezone", layer_at("display.timezone", timezone.clone().into())));
+    }
+    layers
+}
+
+/// A key as written in TOML, quoted unless it's bare.
+fn toml_key(key: &str) -> String {
+    if !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+/// The settings in `table` as dotted keys relative to it, with their full paths.
+fn flatten_settings(table: &toml::Table, prefix: &str, relative: &str, out: &mut Vec<(String, String, toml::Value)>) {
+    for (key, value) in table {
+        let path = format!("{}.{}", prefix, key);
+        let name = if relative.is_empty() { toml_key(key) } else { format!("{}.{}", relative, toml_key(key)) };
+        match value {
+            toml::Value::Table(inner) => flatten_settings(inner, &path, &name, out),
+            value => out.push((path, name, value.clone())),
+        }
+    }
+}
+
+/// The configuration as TOML with each value's origin as a trailing comment.
+pub(crate) fn config_with_origins(config: &Config, origins: &BTreeMap<String, Origin>) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut out = String::new();
+    for (section, values) in toml::Table::try_from(config)? {
+        let Some(values) = values.as_table() else { continue };
+        let mut settings = Vec::new();
+        flatten_settings(values, &section, "", &mut settings);
+        if settings.is_empty() {
+            continue;
+        }
+        writeln!(out, "[{}]", section).unwrap();
+        for (path, name, value) in settings {
+            let origin = origins.get(&path).map_or_else(|| "default".to_string(), ToString::to_string);
+            writeln!(out, "{} = {}  {}", name, value, format!("# {}", origin).dimmed()).unwrap();
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
@@ -1,29 +1,513 @@
 // src/main.rs
 
 use chrono::{prelude::*, Duration};
-use clap::Parser;
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use directories::ProjectDirs;
 use comfy_table::{
-    modifiers::UTF8_ROUND_CORNERS, presets::UTF8_FULL, Cell, Color, ContentArrangement, Table,
+    modifiers::UTF8_ROUND_CORNERS, presets::{ASCII_FULL, UTF8_FULL}, Attribute, Cell, Color, ColumnConstraint, ContentArrangement, Table, Width,
 };
 use colored::*;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
 use serde::{Deserialize, Serialize};
-use std::{error::Error, fs, path::Path, sync::Arc, thread};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    error::Error,
+    ffi::OsString,
+    fmt::Write as _,
+    fs,
+    io::{IsTerminal, Read as _, Write as _},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock, PoisonError,
+    },
+    thread,
+};
+
+mod config;
+use config::{active_profile, config_with_origins, load_or_create_config, read_config_at, Origin, ResolvedConfig};
 
 // --- Configuration & Constants ---
 const CONFIG_DIR: &str = "/etc/bstt";
 const CONFIG_FILE: &str = "config.toml";
+const BUILDINGS: &str = include_str!("../data/buildings.toml");
+const BANK_HOLIDAYS: &str = include_str!("../data/bank_holidays.json");
+const BANK_HOLIDAYS_URL: &str = "https://www.gov.uk/bank-holidays.json";
+const BANK_HOLIDAYS_FILE: &str = "bank_holidays.json";
+/// Bristol's bank holidays are England and Wales's.
+const BANK_HOLIDAY_DIVISION: &str = "england-and-wales";
+const BRISTOL_BASE_URL: &str = "https://app.bristol.ac.uk";
+const CAMPUSM_TIMETABLE_PATH: &str = "/campusm/sso/cal2/Student%20Timetable";
+const USER_AGENT: &str = concat!("bstt/", env!("CARGO_PKG_VERSION"), " (Linux CLI Timetable Tool)");
+const LAST_VIEW_FILE: &str = "last_view.json";
+const CACHE_FILE_BINARY: &str = "events.bin";
+const CACHE_FILE_JSON: &str = "events.json";
+const NO_CACHE: &str = "There is no cached timetable yet.";
+const IMPORTED_FILE: &str = "imported.json";
+const SNOOZED_FILE: &str = "snoozed.json";
+const FOCUS_FILE: &str = "focus.json";
+const CHANGES_FILE: &str = "changes.jsonl";
+const CHANGES_NOTIFIED_FILE: &str = "changes_notified.json";
+const FETCH_HISTORY_FILE: &str = "fetch_history.jsonl";
+const CACHE_MAGIC: &[u8; 4] = b"BSTT";
+// Bump whenever `EventCache` or `CachedEvent` changes shape.
+const CACHE_SCHEMA_VERSION: u32 = 3;
+// A numbered view older than this can no longer be referred to by follow-up commands.
+const LAST_VIEW_MAX_AGE_MINUTES: i64 = 60;
+const DEFAULT_FETCH_DAYS_PAST: i64 = 1;
+const DEFAULT_FETCH_DAYS_FUTURE: i64 = 90;
 
 // --- Data Structures (FIXED) ---
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct Config {
     api: ApiConfig,
+    #[serde(default)]
+    display: DisplayConfig,
+    #[serde(default)]
+    log: LogConfig,
+    #[serde(default)]
+    mini: MiniConfig,
+    #[serde(default)]
+    cache: CacheConfig,
+    #[serde(default)]
+    academic_calendar: AcademicCalendar,
+    #[serde(default)]
+    alarm: AlarmConfig,
+    #[serde(default)]
+    serve: ServeConfig,
+    #[serde(default)]
+    filter: FilterConfig,
+    #[serde(default)]
+    changes: ChangesConfig,
+    #[serde(default)]
+    telemetry: TelemetryConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// What bstt records about its own requests. Nothing here is ever sent anywhere.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct TelemetryConfig {
+    /// Keep a history of API requests in the data directory for `bstt api-stats`.
+    local_history: bool,
+    /// How many requests the history keeps; the oldest go first.
+    history_entries: usize,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig { local_history: true, history_entries: 1000 }
+    }
+}
+
+/// Which changes to the timetable are logged to `changes.jsonl`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct ChangesConfig {
+    /// Only changes to events in the next this many days are logged; 0 stops logging.
+    days: i64,
+    /// Whether `bstt notify` and `bstt serve` send a desktop notification for each change.
+    notify: bool,
+}
+
+impl Default for ChangesConfig {
+    fn default() -> Self {
+        ChangesConfig { days: 7, notify: true }
+    }
+}
+
+/// Filters applied to every fetched event, before any view sees them.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct FilterConfig {
+    /// Drop events shorter than this many minutes, such as the timetabling system's zero-length
+    /// placeholders; 0 keeps everything.
+    min_event_duration_minutes: i64,
+}
+
+/// `bstt serve`'s settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct ServeConfig {
+    /// When set, `/` and `/calendar.ics` need `?token=` with this value.
+    token: Option<String>,
+}
+
+/// `bstt alarm`'s settings.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct AlarmConfig {
+    /// How long before the first event to wake up, e.g. "75m" or "1h30m".
+    lead: String,
+    /// What `bstt alarm --set` runs through `sh -c`. `{time}` (HH:MM), `{datetime}` (RFC 3339)
+    /// and `{title}` are replaced with shell-quoted values.
+    command: Option<String>,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        AlarmConfig { lead: "75m".to_string(), command: None }
+    }
+}
+
+/// Term dates, for numbering teaching weeks. Weeks aren't numbered until a semester has both
+/// its start and end.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+struct AcademicCalendar {
+    semester_1_start: Option<NaiveDate>,
+    semester_1_end: Option<NaiveDate>,
+    semester_2_start: Option<NaiveDate>,
+    semester_2_end: Option<NaiveDate>,
+    /// The Mondays of reading weeks, which aren't counted as teaching weeks.
+    reading_weeks: Vec<NaiveDate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheConfig {
+    #[serde(default)]
+    format: CacheFormat,
+    /// Use the cache instead of the API when it's at most this many minutes old; 0 always fetches.
+    #[serde(default = "default_cache_ttl_minutes")]
+    ttl_minutes: i64,
+    /// When the API can't be reached, cached data older than this many hours is shown in yellow.
+    #[serde(default = "default_stale_warning_hours")]
+    stale_warning_hours: i64,
+    /// ...and older than this many in red, with mini mode showing its age (`!26h`).
+    #[serde(default = "default_stale_alert_hours")]
+    stale_alert_hours: i64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            format: CacheFormat::default(),
+            ttl_minutes: default_cache_ttl_minutes(),
+            stale_warning_hours: default_stale_warning_hours(),
+            stale_alert_hours: default_stale_alert_hours(),
+        }
+    }
+}
+
+fn default_cache_ttl_minutes() -> i64 {
+    10
+}
+
+fn default_stale_warning_hours() -> i64 {
+    6
+}
+
+fn default_stale_alert_hours() -> i64 {
+    24
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum CacheFormat {
+    /// Quick to load on every status bar tick.
+    #[default]
+    Binary,
+    /// For reading with jq.
+    Json,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct LogConfig {
+    /// Where warnings and errors go when stderr isn't a terminal.
+    #[serde(default)]
+    file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 struct ApiConfig {
     cookie: String,
+    /// Which university's API this is.
+    #[serde(default)]
+    provider: Provider,
+    /// Where the API is served from, e.g. "https://app.bath.ac.uk". Defaults to Bristol's for
+    /// the bristol provider.
+    #[serde(default)]
+    base_url: Option<String>,
+    /// The timetable's path under `base_url` (generic_campusm only).
+    #[serde(default)]
+    path: Option<String>,
+    /// The feed's names for event fields that it calls something other than Bristol does, keyed
+    /// by title, type, start, end, location, lecturer, id or level (generic_campusm only).
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+    /// Split the fetch window into requests of this many days, so one failure doesn't lose everything.
+    #[serde(default)]
+    chunk_days: Option<i64>,
+    /// How many days before today to fetch. Views of earlier dates stretch the window to reach them.
+    #[serde(default = "default_fetch_days_past")]
+    fetch_days_past: i64,
+    /// How many days after today to fetch, stretched in the same way.
+    #[serde(default = "default_fetch_days_future")]
+    fetch_days_future: i64,
+    /// Replaces the default `bstt/<version>` User-Agent.
+    #[serde(default)]
+    user_agent: Option<String>,
+    /// Sent with every request, overriding the default headers (but never the cookie).
+    #[serde(default)]
+    extra_headers: BTreeMap<String, String>,
+    /// Fail on events with implausible times instead of flagging them.
+    #[serde(default)]
+    strict: bool,
+    /// Where to keep cookies the server sets (besides `cookie`) between runs, e.g.
+    /// "~/.local/share/bstt/cookies.json". Session cookies aren't kept.
+    #[serde(default)]
+    cookie_jar_file: Option<PathBuf>,
+    /// Keep the API's text as sent, rather than decoding HTML entities, repairing mis-decoded
+    /// punctuation and tidying whitespace. It's still put in Unicode NFC.
+    #[serde(default)]
+    raw_text: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Provider {
+    #[default]
+    Bristol,
+    /// Another university's CampusM, with its path and field names set in `[api]`.
+    GenericCampusm,
+}
+
+fn default_fetch_days_past() -> i64 {
+    DEFAULT_FETCH_DAYS_PAST
+}
+
+fn default_fetch_days_future() -> i64 {
+    DEFAULT_FETCH_DAYS_FUTURE
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct DisplayConfig {
+    /// Number the rows automatically when writing to a terminal, for use with `bstt show`.
+    #[serde(default)]
+    numbered: bool,
+    /// Add a map link for recognised buildings to the Location column.
+    #[serde(default)]
+    show_map_links: bool,
+    /// Whether to make titles and locations clickable, in terminals that support it.
+    #[serde(default)]
+    hyperlinks: HyperlinkMode,
+    /// Links for the titles of events whose titles contain the key, e.g. the unit's Blackboard page.
+    #[serde(default)]
+    unit_links: BTreeMap<String, String>,
+    /// Events longer than this are flagged as suspicious.
+    #[serde(default = "default_suspicious_duration_hours")]
+    suspicious_duration_hours: i64,
+    /// How many days from Monday the week views always show; later days only appear when busy.
+    #[serde(default = "default_week_length")]
+    week_length: u32,
+    /// Leave today's finished events out of the day view, as `--no-prev` does.
+    #[serde(default)]
+    hide_finished: bool,
+    /// Leave Saturday and Sunday out of the week views, even when something is on.
+    #[serde(default)]
+    skip_weekends: bool,
+    /// Count the day view's `+N` in teaching days, as `--teaching-day` does.
+    #[serde(default)]
+    teaching_days: bool,
+    /// A unit whose events are emphasised everywhere, usually set with `bstt focus`.
+    #[serde(default)]
+    focus: Option<String>,
+    /// The day view warns about days with more contact time than this many hours; 0 never warns.
+    #[serde(default = "default_busy_hours")]
+    busy_hours: f64,
+    /// The hours `bstt gantt`'s timeline runs between.
+    #[serde(default = "default_gantt_start_hour")]
+    gantt_start_hour: u32,
+    #[serde(default = "default_gantt_end_hour")]
+    gantt_end_hour: u32,
+    /// An IANA timezone to show times in, instead of the system's (which cron may not set).
+    #[serde(default)]
+    timezone: Option<String>,
+    /// Colours for the titles of particular units in the day view, e.g. `Physics = "cyan"`: a
+    /// colour name or "#rrggbb". Titles match as `bstt focus` does, compressed or not.
+    #[serde(default)]
+    module_colors: BTreeMap<String, String>,
+    /// Events whose titles contain any of these float to the top of the day view.
+    #[serde(default)]
+    pinned: Vec<String>,
+    /// `"colorblind"` for a palette that doesn't rely on telling red from green.
+    #[serde(default)]
+    theme: Theme,
+    /// Set to false for 12-hour times ("01:30 PM").
+    #[serde(default = "default_time_format_24h")]
+    time_format_24h: bool,
+    /// Show times to the second, for telling apart events that start in the same minute.
+    #[serde(default)]
+    show_seconds: bool,
+    /// Draw tables, the spinner and marks with ASCII only, for fonts without box drawing or
+    /// braille. Unset, it's decided by whether the locale is UTF-8.
+    #[serde(default)]
+    ascii: Option<bool>,
+    #[serde(default)]
+    day_weekday_labels: Option<WeekdayLabel>,
+    #[serde(default)]
+    week_weekday_labels: Option<WeekdayLabel>,
+    /// The day weeks begin on, which decides "this Friday" from "next Friday" in day labels.
+    #[serde(default = "default_week_start")]
+    week_start: Weekday,
+    /// Row backgrounds in HTML output by event type (lower case), e.g. `lecture = "#dbeafe"`.
+    #[serde(default)]
+    html_colors: BTreeMap<String, String>,
+    /// How hard short views compress titles: 0 not at all, 1 symbols only, 2 also abbreviates
+    /// words, 3 also abbreviates whole unit names.
+    #[serde(default = "default_density")]
+    density: u8,
+    /// Drop "Grp" words from compressed titles; set to false to keep your group in sight.
+    #[serde(default = "default_strip_groups")]
+    strip_groups: bool,
+    /// The widest the day view's Event column gets; longer titles wrap at word boundaries.
+    #[serde(default)]
+    max_title_width: Option<u16>,
+    /// Cut titles longer than `max_title_width` short with "…" instead of wrapping them.
+    #[serde(default)]
+    truncate_titles: bool,
+    /// Events whose location contains any of these (ignoring case) happen online.
+    #[serde(default = "default_online_locations")]
+    online_locations: Vec<String>,
+    /// ...as do those whose title contains any of these.
+    #[serde(default = "default_online_titles")]
+    online_titles: Vec<String>,
+    /// Colour the day table's locations by building (`"location"`).
+    #[serde(default)]
+    color_by: Option<ColorBy>,
+    /// How many colours `color_by` shares out between buildings, up to 8.
+    #[serde(default = "default_location_palette_size")]
+    location_palette_size: usize,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        // Keep in step with the serde defaults above.
+        toml::from_str("").expect("every display setting has a default")
+    }
+}
+
+/// The words and separators in the `--mini`/`--waybar` status line.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct MiniConfig {
+    current: String,
+    next: String,
+    border: String,
+    end: String,
+    /// Shown when nothing is left today.
+    blank: String,
+    /// Shown instead of `blank` on a bank holiday, followed by the holiday's name.
+    prefix_holiday: String,
+    /// Shown instead of `blank` during a reading week.
+    prefix_reading_week: String,
+    /// Shown when the timetable couldn't be fetched.
+    error: String,
+    /// Between the title and the location.
+    separator: String,
+    /// Between the location and the start time.
+    at: String,
+    /// How long after its end an event still counts as current; negative stops it counting
+    /// that long before the end.
+    current_grace_minutes: i64,
+}
+
+impl Default for MiniConfig {
+    fn default() -> Self {
+        MiniConfig {
+            current: "CUR".to_string(),
+            next: "NXT".to_string(),
+            border: "BRD".to_string(),
+            end: "END".to_string(),
+            blank: "TTB: BLK".to_string(),
+            prefix_holiday: "HOL".to_string(),
+            prefix_reading_week: "RDG".to_string(),
+            error: "TTB: ERR".to_string(),
+            separator: " | ".to_string(),
+            at: " @ ".to_string(),
+            current_grace_minutes: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum HyperlinkMode {
+    /// When writing to a terminal known to support OSC 8 links.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+fn default_suspicious_duration_hours() -> i64 {
+    6
+}
+
+fn default_week_length() -> u32 {
+    5
+}
+
+fn default_busy_hours() -> f64 {
+    6.0
+}
+
+fn default_gantt_start_hour() -> u32 {
+    8
+}
+
+fn default_gantt_end_hour() -> u32 {
+    22
+}
+
+fn default_time_format_24h() -> bool {
+    true
+}
+
+fn default_location_palette_size() -> usize {
+    6
+}
+
+fn default_online_locations() -> Vec<String> {
+    ["online", "virtual", "ms teams", "microsoft teams", "zoom", "blackboard collaborate", "remote delivery"].map(String::from).to_vec()
+}
+
+fn default_online_titles() -> Vec<String> {
+    ["(online)", "[online]", "- online", "virtual session"].map(String::from).to_vec()
+}
+
+fn default_week_start() -> Weekday {
+    Weekday::Mon
+}
+
+fn default_density() -> u8 {
+    3
+}
+
+fn default_strip_groups() -> bool {
+    true
+}
+
+/// What decides the colour of a day table's locations.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum ColorBy {
+    /// The building, so each building keeps its own colour
+    Location,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum WeekdayLabel {
+    /// Wednesday
+    Full,
+    /// Wed
+    Short,
+    /// W
+    Letter,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -31,6 +515,13 @@ struct ApiResponse {
     events: Vec<Event>,
 }
 
+/// The response before the events are read one by one, so that a single malformed event
+/// doesn't lose the rest.
+#[derive(Deserialize)]
+struct RawApiResponse {
+    events: Vec<serde_json::Value>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct Event {
     #[serde(rename = "desc1")]
@@ -44,6 +535,68 @@ struct Event {
     // BUG FIX: Changed teacher_name to an Option to handle cases where it's missing from the API response.
     #[serde(rename = "teacherName")]
     teacher_name: Option<String>,
+    /// The event's id in CampusM, which the API sends as either a string or a number.
+    #[serde(rename = "id", default, deserialize_with = "string_or_number")]
+    booking_id: Option<String>,
+    /// The year of study the session is for, which some departments include.
+    #[serde(default, alias = "yearOfStudy", deserialize_with = "string_or_number")]
+    level: Option<String>,
+    /// Set when validation found the event's times implausible.
+    #[serde(skip)]
+    issue: Option<EventIssue>,
+}
+
+fn string_or_number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(s)) => Some(s),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+impl Event {
+    /// The event with its text in Unicode NFC, so that a name written with combining accents
+    /// matches, hashes and dedupes like the precomposed one. Every event is built through this,
+    /// whatever its source and even with --raw.
+    fn composed(mut self) -> Self {
+        use unicode_normalization::{is_nfc, UnicodeNormalization};
+        let fields = [&mut self.title, &mut self.event_type, &mut self.location].into_iter().chain(self.teacher_name.as_mut());
+        for field in fields.filter(|field| !is_nfc(field)) {
+            *field = field.nfc().collect();
+        }
+        self
+    }
+}
+
+/// A link to the event's page in the CampusM app, when the feed gave it an id.
+fn deep_link(event: &Event) -> Option<String> {
+    let base_url = CAMPUSM_BASE_URL.get().map_or(BRISTOL_BASE_URL, String::as_str);
+    event.booking_id.as_ref().map(|id| format!("{}/campusm/event/{}", base_url, id))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum EventIssue {
+    /// The feed had the end before the start; the times have been swapped.
+    Reversed,
+    /// Longer than `suspicious_duration_hours`.
+    TooLong,
+}
+
+impl EventIssue {
+    fn describe(self) -> &'static str {
+        match self {
+            EventIssue::Reversed => "⚠ end was before start",
+            EventIssue::TooLong => "⚠ suspicious duration",
+        }
+    }
+}
+
+/// The numbered rows of the last full view, so follow-up commands can refer to events by number.
+#[derive(Serialize, Deserialize, Debug)]
+struct LastView {
+    created_at: DateTime<FixedOffset>,
+    fingerprint: String,
+    ids: Vec<String>,
 }
 
 // --- CLI Argument Parsing ---
@@ -51,288 +604,6064 @@ struct Event {
 #[derive(Parser, Debug)]
 #[command(author, version, about = "Fetches and displays University of Bristol student timetable.", long_about = None)]
 struct Cli {
-    /// Day offset from today for full timetable view. E.g., 0 for today, +1 for tomorrow.
-    #[arg(default_value = "0")]
-    day_offset: String,
+    /// Fetch this many days either side of today instead of the configured window
+    #[arg(long, value_name = "DAYS", global = true)]
+    window: Option<i64>,
+
+    /// No longer needed: the fetch window always stretches to the requested date
+    #[arg(long, global = true, hide = true)]
+    auto_extend: bool,
+
+    /// Show times on the 12-hour clock (e.g. "01:30 PM")
+    #[arg(long = "12h", global = true)]
+    twelve_hour: bool,
+
+    /// Show times to the second (e.g. "09:00:30"), overriding `[display] show_seconds`
+    #[arg(long, global = true)]
+    seconds: bool,
+
+    /// Draw tables, the spinner and marks with ASCII only, overriding `[display] ascii`
+    #[arg(long, global = true, overrides_with = "unicode")]
+    ascii: bool,
+
+    /// Draw with Unicode even when the locale doesn't look like UTF-8
+    #[arg(long, global = true, overrides_with = "ascii")]
+    unicode: bool,
+
+    /// Keep group words such as "Grp 2" in compressed titles, overriding `[display] strip_groups`
+    #[arg(long, global = true, overrides_with = "strip_groups")]
+    keep_groups: bool,
+
+    /// Drop group words from compressed titles, as is the default
+    #[arg(long, global = true, overrides_with = "keep_groups")]
+    strip_groups: bool,
+
+    /// Show times in UTC instead of the local or configured timezone
+    #[arg(long, global = true)]
+    utc: bool,
+
+    /// Write the cache as JSON (for inspecting with jq) or binary, overriding `[cache] format`
+    #[arg(long, value_enum, global = true)]
+    cache_format: Option<CacheFormat>,
+
+    /// Show times (and decide which day events fall on) in this IANA timezone, e.g. America/New_York
+    #[arg(long, value_name = "TZ", global = true, conflicts_with = "utc")]
+    timezone: Option<String>,
+
+    /// Print diagnostic detail to stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
 
-    /// Enable compact, single-line output for status bars like Polybar
+    /// Fail instead of flagging events with implausible times
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Show titles, locations and the rest as the API sends them, without tidying them up (bar Unicode normalisation)
+    #[arg(long, global = true)]
+    raw: bool,
+
+    /// Ask the API even if the cache is recent enough to use
+    #[arg(long, visible_alias = "no-cache", global = true)]
+    refresh: bool,
+
+    /// How hard mini and the week views compress titles, from 0 (not at all) to 3 (the most), overriding `[display] density`
+    #[arg(long, value_name = "N", global = true, value_parser = clap::value_parser!(u8).range(0..=3))]
+    density: Option<u8>,
+
+    /// Read the config from this file instead of /etc/bstt/config.toml, or from standard input with `-`
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// Apply the config file's [profile.NAME] section over the rest of it (or set BSTT_PROFILE)
+    #[arg(long, value_name = "NAME", global = true)]
+    profile: Option<String>,
+
+    /// Show what's on as of this time today (HH:MM) instead of now, in mini, waybar and the day view
+    #[arg(long, value_name = "HH:MM", global = true)]
+    at: Option<String>,
+
+    /// Leave out events starting before this date (YYYY-MM-DD), fetching back to it if needed
+    #[arg(long, value_name = "DATE", global = true)]
+    since: Option<NaiveDate>,
+
+    /// Leave out events starting after this date (YYYY-MM-DD), fetching up to it if needed
+    #[arg(long, value_name = "DATE", global = true)]
+    until: Option<NaiveDate>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Which events a view shows.
+#[derive(Args, Debug)]
+struct FilterArgs {
+    /// Only show events of these types (comma-separated, case-insensitive)
+    #[arg(long = "type", value_delimiter = ',')]
+    types: Vec<String>,
+
+    /// Only show events whose title, location, type or lecturer contains this text
     #[arg(long)]
-    mini: bool,
+    search: Option<String>,
+
+    /// Hide events whose title, type or location contains any of these terms (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Hide events shorter than this many minutes, such as zero-length placeholders
+    #[arg(long, visible_alias = "min-duration-minutes", value_name = "MINUTES", default_value_t = 0)]
+    min_duration: i64,
 }
 
-// --- Core Logic ---
+/// Options shared by the day and week views.
+#[derive(Args, Debug)]
+struct ViewArgs {
+    /// Day offset from today. E.g., 0 for today, +1 for tomorrow, -1 for yesterday
+    #[arg(default_value = "0", allow_negative_numbers = true)]
+    day_offset: String,
 
-fn load_or_create_config() -> Result<Config, Box<dyn Error + Send + Sync>> {
-    let config_dir = Path::new(CONFIG_DIR);
-    let config_path = config_dir.join(CONFIG_FILE);
+    #[command(flatten)]
+    filters: FilterArgs,
 
-    if !config_path.exists() {
-        eprintln!("{} Config file not found at '{}'.", "Warning:".yellow(), config_path.display());
-        if !config_dir.exists() {
-            fs::create_dir_all(config_dir).map_err(|e| format!("Failed to create config directory at '{}': {}. Try `sudo mkdir -p {}`.", config_dir.display(), e, config_dir.display()))?;
-        }
-        let template = "[api]\ncookie = \"YourCookieHere\"\n";
-        fs::write(&config_path, template).map_err(|e| format!("Failed to create config file at '{}': {}.", config_path.display(), e))?;
-        eprintln!("A template config has been created. Edit it with your cookie: `sudo nano {}`", config_path.display());
-        std::process::exit(1);
-    }
+    /// How to label weekdays (defaults to full names for a day, short names for a week)
+    #[arg(long, value_enum)]
+    weekday_labels: Option<WeekdayLabel>,
 
-    let config_str = fs::read_to_string(&config_path)?;
-    let config: Config = toml::from_str(&config_str)?;
+    /// Show a link to each event's page in the CampusM app, where available
+    #[arg(long)]
+    show_links: bool,
 
-    if config.api.cookie == "YourCookieHere" {
-        eprintln!("{} Your config at '{}' still contains the default value.", "Error:".red().bold(), config_path.display());
-        eprintln!("Please replace 'YourCookieHere' with your actual cookie.");
-        std::process::exit(1);
-    }
-    Ok(config)
-}
+    /// Add a Level column with the year of study, for feeds that include it
+    #[arg(long)]
+    show_level: bool,
 
-// --- fetch_events (MODIFIED WITH BETTER ERROR HANDLING) ---
-fn fetch_events(config: &Config) -> Result<ApiResponse, Box<dyn Error + Send + Sync>> {
-    let today = Utc::now();
-    let start_date = (today - Duration::days(90)).format("%Y-%m-%dT%H:%M:%S.000Z").to_string();
-    let end_date = (today + Duration::days(90)).format("%Y-%m-%dT%H:%M:%S.000Z").to_string();
-    
-    let url = format!("https://app.bristol.ac.uk/campusm/sso/cal2/Student%20Timetable?start={}&end={}", start_date, end_date);
-
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .get(url)
-        .header("Cookie", &config.api.cookie)
-        .header("User-Agent", "bstt/0.4.0 (Linux CLI Timetable Tool)")
-        .header("Accept", "*/*")
-        .header("Accept-Language", "en-US,en;q=0.5")
-        .header("Referer", "https://app.bristol.ac.uk/campusm/home")
-        .header("X-Requested-With", "XMLHttpRequest")
-        .header("pragma", "no-cache")
-        .header("cache-control", "no-cache")
-        .send()?;
-    
-    let status = response.status();
-    if !status.is_success() {
-        let body = response.text().unwrap_or_else(|_| "Could not read response body".to_string());
-        return Err(format!("API request failed with status: {}. Server response:\n{}", status, body).into());
-    }
+    /// Show back-to-back sessions with the same title, type and location as one block
+    #[arg(long)]
+    merge_adjacent: bool,
 
-    // IMPROVED ERROR HANDLING: Read body as text first, then attempt to parse.
-    // This allows us to include the problematic body in the error message.
-    let body_text = response.text()?;
-    let data: ApiResponse = serde_json::from_str(&body_text)
-        .map_err(|e| {
-            format!(
-                "Failed to decode JSON response from server. Error: {}\n\n---\nReceived Body:\n{}---",
-                e, body_text
-            )
-        })?;
+    /// List the latest events first; pinned events still come before the rest
+    #[arg(long)]
+    reverse: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    format: OutputFormat,
 
-    Ok(data)
+    /// Also copy the output (without colours) to the clipboard
+    #[arg(long)]
+    clipboard: bool,
+
+    /// Also write the events shown to this file as iCalendar
+    #[arg(long, value_name = "FILE")]
+    ics_file: Option<PathBuf>,
+
+    /// Also write the events shown to this file as JSON
+    #[arg(long, value_name = "FILE")]
+    json_file: Option<PathBuf>,
+
+    /// Lay tables out to this many columns instead of the terminal's width (200 when piped)
+    #[arg(long, value_name = "COLUMNS")]
+    width: Option<u16>,
 }
 
-// --- Full Timetable Display (FIXED) ---
-fn display_timetable(events_data: ApiResponse, target_date: NaiveDate) {
-    let mut daily_events: Vec<Event> = events_data.events.into_iter().filter(|event| {
-        if let Ok(start_time) = DateTime::parse_from_rfc3339(&event.start) {
-            start_time.with_timezone(&Local).date_naive() == target_date
-        } else { false }
-    }).collect();
+/// The numbered day view a follow-up command's row number refers to. It takes the flags the
+/// view was shown with, so that a number is never looked up in a different view's rows.
+#[derive(Args, Debug)]
+struct NumberedViewArgs {
+    /// The numbered view's day offset from today
+    #[arg(long = "day", value_name = "OFFSET", default_value = "0", allow_negative_numbers = true)]
+    day_offset: String,
 
-    daily_events.sort_by(|a, b| a.start.cmp(&b.start));
-    
-    let date_str = target_date.format("%A, %d %B %Y").to_string();
-    let day_diff = target_date.signed_duration_since(Local::now().date_naive()).num_days();
-    let day_label = match day_diff { 0 => " (Today)", 1 => " (Tomorrow)", -1 => " (Yesterday)", _ => "" };
-    
-    println!(" {} {}{}", "Timetable for".bold(), date_str.bold(), day_label.bold());
+    #[command(flatten)]
+    filters: FilterArgs,
 
-    if daily_events.is_empty() {
-        println!("\n{}", "No events scheduled for this day.".green());
-        return;
-    }
+    /// The numbered view's --pin terms (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pin: Vec<String>,
 
-    let mut table = Table::new();
-    table.load_preset(UTF8_FULL).apply_modifier(UTF8_ROUND_CORNERS).set_content_arrangement(ContentArrangement::Dynamic);
-    
-    table.set_header(vec![
-        Cell::new("Time").fg(Color::Magenta), Cell::new("Type").fg(Color::Magenta),
-        Cell::new("Event").fg(Color::Magenta), Cell::new("Location").fg(Color::Magenta),
-        Cell::new("Lecturer").fg(Color::Magenta),
-    ]);
+    /// The numbered view merged back-to-back sessions
+    #[arg(long)]
+    merge_adjacent: bool,
 
-    for event in daily_events {
-        let start_time = DateTime::parse_from_rfc3339(&event.start).unwrap();
-        let end_time = DateTime::parse_from_rfc3339(&event.end).unwrap();
-        let time_str = format!("{} - {}", start_time.with_timezone(&Local).format("%H:%M"), end_time.with_timezone(&Local).format("%H:%M"));
-        
-        // BUG FIX: Gracefully handle the Option<String> for teacher_name.
-        let main_lecturer = event.teacher_name
-            .as_deref() // Convert Option<String> to Option<&str>
-            .unwrap_or("") // Provide a default empty string if None
-            .split(',')
-            .next()
-            .unwrap_or("")
-            .trim();
+    /// The numbered view listed the latest events first
+    #[arg(long)]
+    reverse: bool,
 
-        table.add_row(vec![
-            Cell::new(time_str).fg(Color::Cyan), Cell::new(event.event_type).fg(Color::Yellow),
-            Cell::new(event.title), Cell::new(event.location).fg(Color::Green),
-            Cell::new(main_lecturer).fg(Color::Blue),
-        ]);
-    }
-    println!("{}", table);
+    /// The numbered view left out today's finished events
+    #[arg(long, overrides_with = "prev")]
+    no_prev: bool,
+
+    /// The numbered view showed today's finished events
+    #[arg(long, overrides_with = "no_prev")]
+    prev: bool,
+
+    /// The numbered view counted its offset in teaching days
+    #[arg(long, overrides_with = "calendar_day")]
+    teaching_day: bool,
+
+    /// The numbered view counted its offset in calendar days
+    #[arg(long, overrides_with = "teaching_day")]
+    calendar_day: bool,
 }
 
-// --- Compression Helpers (Unchanged) ---
-fn apply_transformations(mut s: String, rules: &[(&str, &str)]) -> String {
-    for (find, replace) in rules.iter() {
-        s = s.replace(find, replace);
-    }
-    s
+/// Where `bstt serve` listens.
+#[derive(Args, Debug)]
+struct ServeAddress {
+    #[arg(long, default_value_t = 8080)]
+    port: u16,
+
+    /// The address to listen on; 0.0.0.0 makes the server reachable from other devices
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1")]
+    bind: String,
 }
 
-fn compress_title(title: &str) -> String {
-    let compound_rules = [
-        ("Software Engineering", "SE"), ("Data Structures", "DS"), ("Intro to AI", "AI"),
-        ("Practical Physics-Computing Lecture", "Labs-Comp Lec"), ("Practical Physics-Computing Drop-in", "Labs-Comp DI"),
-        ("Probability & Statistics for Physicists", "Prob+Stats P"), ("Introductory Mathematics for Physics", "Intro M for P"),
-        ("Intro to Coding and Data Analysis", "Coding+D.A."), ("Core Physics I Problem Class", "Core P PrbCls"),
-        ("Intro Mathematics Examples Class", "Intro M ExCls"), ("Practical Physics", "Labs"), ("Problem Class", "PrbCls"),
-    ];
-    let atomic_rules = [
-        ("Introductory", "Intro"), ("Introduction", "Intro"), ("Mathematics", "M"), ("Physics", "P"),
-        ("Probability", "Prob"), ("Statistics", "Stats"), ("Computing", "Comp"),
-        ("Lecture", "Lec"), ("Tutorial", "Tut"), ("Workshop", "W"), ("Project", "Proj"), ("Assembly", "Asmbly"),
-    ];
-    let symbol_rules = [(" and ", " + "), (" & ", " + "), (" for ", " "), (" of ", " "), (" to ", " ")];
-    let mut processed_title = apply_transformations(title.to_string(), &compound_rules);
-    processed_title = apply_transformations(processed_title, &atomic_rules);
-    processed_title = apply_transformations(processed_title, &symbol_rules);
-    let numerals = [" V", " IV", " III", " II", " I"];
-    for num in numerals.iter() {
-        if processed_title.ends_with(num) {
-            processed_title = processed_title[..processed_title.len() - num.len()].to_string();
-            break;
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    Html,
+    Markdown,
+}
+
+impl OutputFormat {
+    /// The export format, for anything but a table.
+    fn structured(self) -> Option<ExportFormat> {
+        match self {
+            OutputFormat::Table => None,
+            OutputFormat::Json => Some(ExportFormat::Json),
+            OutputFormat::Csv => Some(ExportFormat::Csv),
+            OutputFormat::Html => Some(ExportFormat::Html),
+            OutputFormat::Markdown => Some(ExportFormat::Markdown),
         }
     }
-    let words: Vec<&str> = processed_title.split_whitespace().filter(|word| !word.to_lowercase().starts_with("grp")).collect();
-    words.join(" ")
 }
 
-fn compress_location(location: &str) -> String {
-    let rules = [
-        ("Physics Building", "Phys"), ("Priory Road Complex", "PrioryRd"),
-        ("Biomedical Sciences Building", "BioSci"), ("31-37 St. Michael's Hill", "StMichHill"),
-        ("Queen's Building", "Queens"), ("Chemistry Building", "Chem"), ("Fry Building", "Fry"),
-        ("Lecture Theatre", "LT"), ("Building", "Bldg"), ("Complex", "Cmplx"),
-        (" Room", ""), ("Rear:", ""), (": ", ":"),
-    ];
-    apply_transformations(location.to_string(), &rules)
-}
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show a day's timetable (the default)
+    Day {
+        #[command(flatten)]
+        view: ViewArgs,
 
-// --- Mini-Mode Display (MODIFIED) ---
-fn display_mini_timetable(events_data: ApiResponse) {
-    let now = Local::now();
-    let today = now.date_naive();
-
-    // Get all of today's events and sort them.
-    let mut todays_events: Vec<Event> = events_data.events.into_iter().filter(|event| {
-        if let Ok(start_time) = DateTime::parse_from_rfc3339(&event.start) {
-            start_time.with_timezone(&Local).date_naive() == today
-        } else { false }
-    }).collect();
-    todays_events.sort_by(|a, b| a.start.cmp(&b.start));
+        /// Print a QR code of the first upcoming event, as an iCalendar event for phones to import
+        #[arg(long)]
+        qr: bool,
 
-    // Find the current event.
-    let current_event = todays_events.iter().find(|&event| {
-        let start_time = DateTime::parse_from_rfc3339(&event.start).unwrap().with_timezone(&Local);
-        let end_time = DateTime::parse_from_rfc3339(&event.end).unwrap().with_timezone(&Local);
-        now >= start_time && now < end_time
-    });
+        /// Print a QR code of the Nth event of the day instead of the first upcoming one
+        #[arg(long, value_name = "N")]
+        qr_event: Option<usize>,
 
-    // Find the next upcoming event.
-    let next_event = todays_events.iter().find(|&event| {
-        let start_time = DateTime::parse_from_rfc3339(&event.start).unwrap().with_timezone(&Local);
-        start_time > now
-    });
+        /// Prefix each row with a number that follow-up commands such as `bstt show` can refer to
+        #[arg(long)]
+        numbered: bool,
 
-    if let Some(current) = current_event {
-        // A class is currently in progress.
-        let end_time = DateTime::parse_from_rfc3339(&current.end).unwrap().with_timezone(&Local);
-        let border_time = end_time - Duration::minutes(10);
-        
-        // Check if we are in the 10-minute "border" window before the end.
-        if now >= border_time {
-            if let Some(next) = next_event {
-                // We are in the border and there is another class today.
-                let current_end_str = end_time.format("%H:%M");
-                let next_start_str = DateTime::parse_from_rfc3339(&next.start).unwrap().with_timezone(&Local).format("%H:%M");
-                let next_title = compress_title(&next.title);
-                let next_loc = compress_location(&next.location);
-                print!("BRD {}→{} | {} @ {}", current_end_str, next_start_str, next_title, next_loc);
-            } else {
-                // In the border, but it's the last class of the day. Treat as a normal current class.
-                let current_title = compress_title(&current.title);
-                let current_loc = compress_location(&current.location);
-                print!("CUR {} | {} END {}", current_title, current_loc, end_time.format("%H:%M"));
-            }
-        } else {
-            // Not in the border window yet. Just show the current class.
-            let current_title = compress_title(&current.title);
-            let current_loc = compress_location(&current.location);
-            print!("CUR {} | {} END {}", current_title, current_loc, end_time.format("%H:%M"));
-        }
-    } else if let Some(next) = next_event {
-        // No current class, but there is a next one today.
-        let next_title = compress_title(&next.title);
-        let next_loc = compress_location(&next.location);
-        let next_start = DateTime::parse_from_rfc3339(&next.start).unwrap().with_timezone(&Local);
-        print!("NXT {} | {} @ {}", next_title, next_loc, next_start.format("%H:%M"));
-    } else {
-        // No current or upcoming classes for the rest of the day.
-        print!("TTB: BLK");
-    }
-}
+        /// Show events whose titles contain any of these terms first (comma-separated), as well as any in `[display] pinned`
+        #[arg(long, value_delimiter = ',')]
+        pin: Vec<String>,
 
+        /// List the buildings visited in order with when each is first entered, instead of the timetable
+        #[arg(long, conflicts_with_all = ["qr", "qr_event"])]
+        rooms: bool,
 
-// --- Main Execution ---
-fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let cli = Cli::parse();
-    let config = load_or_create_config()?;
-    let spinner = ProgressBar::new_spinner();
-    spinner.set_style(ProgressStyle::default_spinner().tick_strings(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]).template("{spinner:.blue} {msg}")?);
-    if !cli.mini { spinner.set_message("Fetching timetable..."); }
-    let config_clone = Arc::new(config);
-    let handle = thread::spawn(move || fetch_events(&config_clone));
-    if !cli.mini {
-        while !handle.is_finished() {
-            spinner.tick();
-            thread::sleep(std::time::Duration::from_millis(50));
-        }
-    }
-    let all_events = match handle.join().unwrap() {
-        Ok(events) => {
-            if !cli.mini { spinner.finish_with_message("✓".green().to_string()); }
-            events
-        },
-        Err(e) => {
-            if !cli.mini { spinner.finish_with_message("✗".red().to_string()); }
-            if cli.mini { print!("TTB: ERR"); return Ok(()); }
-            return Err(e);
-        }
-    };
-    if cli.mini {
-        display_mini_timetable(all_events);
-    } else {
-        let offset: i64 = cli.day_offset.parse().map_err(|_| "Invalid day offset.")?;
-        let target_date = Local::now().date_naive() + Duration::days(offset);
-        display_timetable(all_events, target_date);
-    }
-    Ok(())
-}
+        /// Viewing today, leave out the events that have already finished (`[display] hide_finished`)
+        #[arg(long, overrides_with = "prev")]
+        no_prev: bool,
 
-fn main() {
-    if let Err(e) = run() {
-        eprintln!("{} {}", "Error:".red().bold(), e);
+        /// Show today's finished events too, overriding `[display] hide_finished`
+        #[arg(long, overrides_with = "no_prev")]
+        prev: bool,
+
+        /// Count the offset in teaching days, skipping weekend days with nothing on, so that +1 on a Friday is Monday (`[display] teaching_days`)
+        #[arg(long, overrides_with = "calendar_day")]
+        teaching_day: bool,
+
+        /// Count the offset in calendar days, overriding `[display] teaching_days`
+        #[arg(long, overrides_with = "teaching_day")]
+        calendar_day: bool,
+
+        /// List the event types in the fetched window, most common first, instead of the timetable
+        #[arg(long, group = "list")]
+        list_types: bool,
+
+        /// List the locations in the fetched window, most common first, instead of the timetable
+        #[arg(long, group = "list")]
+        list_locations: bool,
+
+        /// List the lecturers in the fetched window, most common first, instead of the timetable
+        #[arg(long, group = "list")]
+        list_lecturers: bool,
+
+        /// Colour each location by its building, the same colour on every run (`[display] color_by`)
+        #[arg(long, value_enum, value_name = "WHAT")]
+        color_by: Option<ColorBy>,
+    },
+    /// Show the week containing a day
+    Week {
+        #[command(flatten)]
+        view: ViewArgs,
+
+        /// One line per day instead of a grid
+        #[arg(long)]
+        compact: bool,
+
+        /// Leave out Saturday and Sunday even when something is on, like `[display] skip_weekends`
+        #[arg(long)]
+        skip_weekends: bool,
+
+        /// Show this many weeks from the one containing the day, each with a summary
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        weeks: u32,
+    },
+    /// Draw a day's events as bars on a timeline
+    Gantt {
+        /// Day offset from today. E.g., 0 for today, +1 for tomorrow, -1 for yesterday
+        #[arg(default_value = "0", allow_negative_numbers = true)]
+        day_offset: String,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+
+        /// How many columns the timeline spans
+        #[arg(long, value_name = "N", default_value_t = 80)]
+        width: usize,
+
+        /// The hour the timeline starts at, instead of `[display] gantt_start_hour`
+        #[arg(long, value_name = "HOUR")]
+        start: Option<u32>,
+
+        /// The hour the timeline ends at, instead of `[display] gantt_end_hour`
+        #[arg(long, value_name = "HOUR")]
+        end: Option<u32>,
+    },
+    /// Print a day's or week's events as JSON, CSV or HTML
+    Export {
+        /// Day offset of the day (or a day in the week) to export; with --since or --until, the
+        /// whole range is exported instead
+        #[arg(default_value = "0", allow_negative_numbers = true)]
+        day_offset: String,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// Export the whole week containing the day
+        #[arg(long)]
+        week: bool,
+
+        /// Export this many weeks from the one containing the day; implies --week
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        weeks: Option<u32>,
+
+        /// Write to this file instead of standard output
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Open the written file in the default application, e.g. a browser for HTML
+        #[arg(long, requires = "output")]
+        open: bool,
+    },
+    /// Print a single status line for bars like Polybar
+    Mini {
+        /// Escape `%` so that Polybar doesn't read titles as formatting tags
+        #[arg(long)]
+        polybar: bool,
+
+        /// Keep running, printing the line again every --interval seconds
+        #[arg(long)]
+        watch: bool,
+
+        /// With --watch, replace this file with the line instead of printing it, for bars that read a file
+        #[arg(long, value_name = "PATH", requires = "watch")]
+        watch_file: Option<PathBuf>,
+
+        /// How often --watch recomputes the line, in seconds
+        #[arg(long, value_name = "SECONDS", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..), requires = "watch")]
+        interval: u64,
+    },
+    /// Print a JSON object for a Waybar custom module, with the rest of the day as its tooltip
+    Waybar {
+        /// Use plain text in the tooltip, for bars that don't render Pango markup
+        #[arg(long)]
+        no_markup: bool,
+    },
+    /// Serve the timetable over HTTP, as JSON and as a calendar to subscribe to
+    Serve {
+        #[command(flatten)]
+        address: ServeAddress,
+
+        /// How often to fetch the timetable again in the background, in seconds
+        #[arg(long, default_value_t = 600)]
+        cache_interval: i64,
+
+        /// Keep serving in the background after the command returns
+        #[arg(long)]
+        daemonize: bool,
+    },
+    /// List the UK bank holidays from today to the end of the fetch window
+    BankHolidays,
+    /// Download the latest UK bank holidays from gov.uk, replacing the bundled list
+    UpdateHolidays,
+    /// Print the webcal:// URL of `bstt serve`'s calendar, for subscribing in a calendar app
+    WebcalUrl {
+        #[command(flatten)]
+        address: ServeAddress,
+    },
+    /// Show every detail of an event from the last numbered view
+    Show {
+        /// Row number from the last `bstt --numbered` table
+        number: usize,
+
+        #[command(flatten)]
+        view: NumberedViewArgs,
+    },
+    /// Send a desktop notification for each event starting soon; run it from cron every minute
+    Notify {
+        /// How many minutes ahead to look
+        #[arg(long, value_name = "MINUTES", default_value_t = 15)]
+        before: i64,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Suggest when to wake up for tomorrow's first event
+    Alarm {
+        /// How long before the event to wake up, e.g. 75m or 1h30m, instead of `[alarm] lead`
+        #[arg(long, value_name = "DURATION")]
+        lead: Option<String>,
+
+        /// Also run `[alarm] command` with the time, to set the alarm
+        #[arg(long)]
+        set: bool,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+    },
+    /// Print how a title or location is shortened, to check the compression rules
+    Compress {
+        /// The title (or with --location, the location) to compress
+        #[arg(required_unless_present = "stdin")]
+        text: Option<String>,
+
+        /// Compress a location rather than a title
+        #[arg(long)]
+        location: bool,
+
+        /// Print each rule that changed the text, with the text after it
+        #[arg(long)]
+        explain: bool,
+
+        /// Compress each line of standard input instead
+        #[arg(long, conflicts_with = "text")]
+        stdin: bool,
+    },
+    /// Emphasise one unit's events everywhere, or show which unit is in focus
+    Focus {
+        /// Part of the unit's title, e.g. "Software Engineering" (or its short form, "SE")
+        unit: Option<String>,
+
+        /// Stop focusing on a unit
+        #[arg(long, conflicts_with = "unit")]
+        clear: bool,
+    },
+    /// Summarise how reliably the API has answered recently, from the local fetch history
+    ApiStats,
+    /// List the changes to upcoming events seen between fetches, oldest first
+    Changes {
+        /// How many of the most recent changes to list
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        limit: usize,
+    },
+    /// Stop `bstt notify` reminding you about an event
+    Snooze {
+        /// A row number from the last numbered view, or part of the title of an upcoming event
+        event: String,
+
+        #[command(flatten)]
+        view: NumberedViewArgs,
+    },
+    /// Sum up a day in a few lines: when it starts and ends, the longest gap, and anything to watch for
+    Brief {
+        /// Day offset from today. E.g., 0 for today, +1 for tomorrow, -1 for yesterday
+        #[arg(default_value = "0", allow_negative_numbers = true)]
+        day_offset: String,
+
+        /// The day to sum up, as YYYY-MM-DD, instead of an offset
+        #[arg(long, conflicts_with = "day_offset")]
+        date: Option<NaiveDate>,
+
+        #[command(flatten)]
+        filters: FilterArgs,
+
+        /// Print the brief as JSON, e.g. for a phone widget
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a plain summary of a week, for piping into `mail`
+    Report {
+        /// Summarise a whole week (currently the only kind of report)
+        #[arg(long, required = true)]
+        week: bool,
+
+        /// Day offset of a day in the week to report on. Defaults to the coming week
+        #[arg(allow_hyphen_values = true)]
+        day_offset: Option<String>,
+
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+
+        /// Print only a suggested e-mail subject line
+        #[arg(long)]
+        subject: bool,
+    },
+    /// Find the times everyone is free on a day, given each person's config file
+    Shared {
+        /// Config files with each person's cookie (comma-separated)
+        #[arg(long, value_name = "FILES", value_delimiter = ',', required = true)]
+        cookies: Vec<PathBuf>,
+
+        /// The day to compare, as YYYY-MM-DD. Defaults to today
+        #[arg(long)]
+        date: Option<NaiveDate>,
+
+        /// Leave out gaps shorter than this many minutes
+        #[arg(long, value_name = "MINUTES", default_value_t = 30)]
+        min_duration: i64,
+
+        /// When the day starts, for gaps before the first event
+        #[arg(long, value_name = "HH:MM", default_value = "09:00")]
+        day_start: String,
+
+        /// When the day ends, for gaps after the last event
+        #[arg(long, value_name = "HH:MM", default_value = "18:00")]
+        day_end: String,
+
+        #[arg(long, value_enum, default_value_t = SharedFormat::Text)]
+        format: SharedFormat,
+    },
+    /// Save a whole academic year: a JSON file per week, a combined CSV and a summary
+    Archive {
+        /// The academic year, e.g. 2024-25: its terms in `[academic_calendar]`, or else September to August
+        #[arg(long)]
+        year: String,
+
+        /// The directory to write into
+        #[arg(long = "out", value_name = "DIR")]
+        out: PathBuf,
+
+        /// Fetch weeks again even if they have already been written
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the events from a calendar file alongside the timetable, replacing any imported before
+    Import {
+        /// An iCalendar (.ics) file, e.g. exported from Google Calendar
+        #[arg(long, value_name = "FILE", required_unless_present = "clear")]
+        ical: Option<PathBuf>,
+
+        /// Forget the imported events
+        #[arg(long, conflicts_with = "ical")]
+        clear: bool,
+    },
+    /// Print some example invocations
+    Examples,
+    /// Inspect or refresh the cached timetable used when the API can't be reached
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Save a new session cookie to the config file
+    Auth {
+        /// The cookie; read from stdin when omitted, to keep it out of shell history
+        cookie: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// Print where the cache is and how old it is
+    Status,
+    /// Fetch the timetable and cache it
+    Refresh,
+    /// Delete the cache
+    Clear,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print where the config file is
+    Path,
+    /// Print the effective configuration, with the cookie hidden
+    Show {
+        /// Note where each value came from: a default, the file, a profile, the environment or a flag
+        #[arg(long)]
+        origins: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ExportFormat {
+    Json,
+    Csv,
+    Html,
+    Ics,
+    Markdown,
+}
+
+const SUBCOMMANDS: &[&str] = &["day", "week", "gantt", "export", "mini", "waybar", "serve", "webcal-url", "bank-holidays", "update-holidays", "show", "brief", "report", "notify", "changes", "api-stats", "alarm", "compress", "focus", "snooze", "shared", "archive", "import", "cache", "config", "auth", "examples", "help"];
+// The global options that take a value, which may come before the subcommand.
+const GLOBAL_VALUE_OPTIONS: &[&str] = &["--window", "--timezone", "--cache-format", "--profile", "--at", "--config", "--density", "--since", "--until"];
+const GLOBAL_FLAGS: &[&str] = &["--auto-extend", "--12h", "--utc", "-v", "--verbose", "--strict", "--refresh", "--no-cache", "--raw", "--seconds", "--ascii", "--unicode", "--keep-groups", "--strip-groups"];
+
+/// Rewrites the pre-subcommand invocations (`bstt`, `bstt +1 --week`, `bstt --mini`, ...) into
+/// their subcommand forms, so existing bar configs and scripts keep working.
+fn legacy_args(mut args: Vec<OsString>) -> Vec<OsString> {
+    let mut rest = args.iter().skip(1).map(|arg| arg.to_str().unwrap_or(""));
+    let first = loop {
+        match rest.next() {
+            Some(option) if GLOBAL_VALUE_OPTIONS.contains(&option) => { rest.next(); }
+            Some(option) if GLOBAL_FLAGS.contains(&option) || GLOBAL_VALUE_OPTIONS.iter().any(|name| option.starts_with(&format!("{}=", name))) => {}
+            other => break other.unwrap_or(""),
+        }
+    };
+    if SUBCOMMANDS.contains(&first) || ["-h", "--help", "-V", "--version"].contains(&first) {
+        return args;
+    }
+    let mut take_flag = |flag: &str| match args.iter().position(|arg| arg == flag) {
+        Some(i) => { args.remove(i); true }
+        None => false,
+    };
+    let inserted: &[&str] = if take_flag("--examples") {
+        &["examples"]
+    } else if take_flag("--serve") {
+        &["serve"]
+    } else if take_flag("--waybar") {
+        &["waybar"]
+    } else if take_flag("--mini") {
+        &["mini"]
+    } else if take_flag("--week") {
+        &["week"]
+    } else if take_flag("--compact-week") {
+        &["week", "--compact"]
+    } else {
+        &["day"]
+    };
+    args.splice(1..1, inserted.iter().map(OsString::from));
+    args
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SharedFormat {
+    Text,
+    Json,
+}
+
+// --- Time Handling ---
+
+/// The timezone times are shown in.
+#[derive(Debug, Clone, Copy)]
+enum DisplayZone {
+    System,
+    /// From `[display] timezone`.
+    Configured(Tz),
+    /// From `--utc` or `--timezone`; views name it, since it's not what the reader is used to.
+    Override(Tz),
+}
+
+static DISPLAY_ZONE: OnceLock<DisplayZone> = OnceLock::new();
+
+/// Converts an instant to the display timezone. Going through UTC means DST gaps and folds
+/// can't produce an ambiguous local time.
+fn to_display<Z: TimeZone>(time: &DateTime<Z>) -> DateTime<FixedOffset> {
+    let utc = time.naive_utc();
+    match DISPLAY_ZONE.get().copied().unwrap_or(DisplayZone::System) {
+        DisplayZone::System => Local.from_utc_datetime(&utc).fixed_offset(),
+        DisplayZone::Configured(zone) | DisplayZone::Override(zone) => zone.from_utc_datetime(&utc).fixed_offset(),
+    }
+}
+
+fn display_now() -> DateTime<FixedOffset> {
+    to_display(&Utc::now())
+}
+
+/// The time of day `--at` asks about, in place of the current time.
+static AT_TIME: OnceLock<NaiveTime> = OnceLock::new();
+
+/// The moment to decide what's on and what's next at: now, or today at the `--at` time.
+fn status_now() -> DateTime<FixedOffset> {
+    let now = display_now();
+    AT_TIME.get()
+        .and_then(|time| from_display(now.date_naive().and_time(*time)))
+        .map_or(now, |at| to_display(&at))
+}
+
+/// The dates `--since` and `--until` limit every view to.
+static DATE_RANGE: OnceLock<(Option<NaiveDate>, Option<NaiveDate>)> = OnceLock::new();
+
+/// Leaves out the events starting outside `--since` and `--until`.
+fn apply_date_range(events: &mut Vec<Event>) {
+    let Some(&(since, until)) = DATE_RANGE.get() else { return };
+    let before = events.len();
+    events.retain(|event| parse_time(&event.start).map(|start| start.date_naive()).is_some_and(|date| {
+        since.is_none_or(|since| date >= since) && until.is_none_or(|until| date <= until)
+    }));
+    log_verbose(format!("--since/--until left out {} of {} events", before - events.len(), before));
+}
+
+/// Drops the events shorter than `[filter] min_event_duration_minutes`.
+fn drop_short_events(events: &mut Vec<Event>, min_minutes: i64) {
+    if min_minutes <= 0 {
+        return;
+    }
+    let before = events.len();
+    events.retain(|event| event_minutes(event) >= min_minutes);
+    log_verbose(format!("[filter] min_event_duration_minutes left out {} of {} events", before - events.len(), before));
+}
+
+/// Reads a length of time like "75m", "1h30m" or "90" (minutes).
+fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if let Ok(minutes) = text.parse::<i64>() {
+        return Some(Duration::minutes(minutes));
+    }
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in text.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'h' | 'm' if !number.is_empty() => {
+                let value: i64 = number.parse().ok()?;
+                number.clear();
+                total += if c == 'h' { Duration::hours(value) } else { Duration::minutes(value) };
+            }
+            _ => return None,
+        }
+    }
+    (!text.is_empty() && number.is_empty()).then_some(total)
+}
+
+fn display_today() -> NaiveDate {
+    display_now().date_naive()
+}
+
+/// The abbreviation of an overriding timezone on `date` (e.g. "EST" or "UTC"), as of local
+/// midday, which is on `date` in every zone and clear of DST changes in the small hours.
+fn zone_label(date: NaiveDate) -> Option<String> {
+    match DISPLAY_ZONE.get() {
+        Some(DisplayZone::Override(zone)) => Some(zone.from_local_datetime(&date.and_hms_opt(12, 0, 0)?).earliest()?.format("%Z").to_string()),
+        _ => None,
+    }
+}
+
+fn format_time(time: DateTime<FixedOffset>, display: &DisplayConfig) -> String {
+    let format = match (display.time_format_24h, display.show_seconds) {
+        (true, false) => "%H:%M",
+        (true, true) => "%H:%M:%S",
+        (false, false) => "%I:%M %p",
+        (false, true) => "%I:%M:%S %p",
+    };
+    time.format(format).to_string()
+}
+
+/// The instant a wall-clock time in the display timezone refers to; the earlier one in a DST fold.
+fn from_display(naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match DISPLAY_ZONE.get().copied().unwrap_or(DisplayZone::System) {
+        DisplayZone::System => Local.from_local_datetime(&naive).earliest().map(|time| time.to_utc()),
+        DisplayZone::Configured(zone) | DisplayZone::Override(zone) => zone.from_local_datetime(&naive).earliest().map(|time| time.to_utc()),
+    }
+}
+
+/// The formats an API timestamp is tried in, after RFC 3339. Those without an offset are taken
+/// as UTC, which is what the API has always sent.
+const TIMESTAMP_FALLBACKS: &[(&str, &str)] = &[
+    ("%Y-%m-%d %H:%M:%S%.f%:z", "space-separated with offset"),
+    ("%Y-%m-%dT%H:%M:%S%.f%z", "offset without a colon"),
+    ("%Y-%m-%dT%H:%M:%S%.f", "no offset"),
+    ("%Y-%m-%d %H:%M:%S%.f", "space-separated without offset"),
+    ("%Y-%m-%dT%H:%M", "no seconds or offset"),
+];
+
+/// Parses an API timestamp, along with a description of the format it matched, so that a
+/// change upstream in how times are written doesn't empty every view.
+fn parse_timestamp_format(text: &str) -> Option<(DateTime<FixedOffset>, &'static str)> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some((time, "RFC 3339"));
+    }
+    TIMESTAMP_FALLBACKS.iter().find_map(|&(format, name)| {
+        DateTime::parse_from_str(text, format).ok()
+            .or_else(|| NaiveDateTime::parse_from_str(text, format).ok().map(|naive| naive.and_utc().fixed_offset()))
+            .map(|time| (time, name))
+    })
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<FixedOffset>> {
+    parse_timestamp_format(text).map(|(time, _)| time)
+}
+
+/// Parses an API timestamp into the display timezone.
+fn parse_time(time: &str) -> Option<DateTime<FixedOffset>> {
+    parse_timestamp(time).map(|time| to_display(&time))
+}
+
+// --- Theme ---
+
+/// What a piece of coloured output means, so that the theme can choose its colour.
+#[derive(Debug, Clone, Copy)]
+enum Role {
+    Heading,
+    Time,
+    EventType,
+    Location,
+    Lecturer,
+    Warning,
+    /// Errors and events with implausible times.
+    Problem,
+    /// Free days and successful fetches.
+    Good,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+enum Theme {
+    #[default]
+    Default,
+    /// The Okabe-Ito palette, which stays distinguishable with the common colour-vision
+    /// deficiencies. In particular it never pairs red with green.
+    Colorblind,
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+impl Theme {
+    fn colour(self, role: Role) -> colored::Color {
+        let rgb = |r, g, b| colored::Color::TrueColor { r, g, b };
+        match (self, role) {
+            (Theme::Default, Role::Heading) => colored::Color::Magenta,
+            (Theme::Default, Role::Time) => colored::Color::Cyan,
+            (Theme::Default, Role::EventType | Role::Warning) => colored::Color::Yellow,
+            (Theme::Default, Role::Location | Role::Good) => colored::Color::Green,
+            (Theme::Default, Role::Lecturer) => colored::Color::Blue,
+            (Theme::Default, Role::Problem) => colored::Color::Red,
+            (Theme::Colorblind, Role::Heading) => rgb(0, 114, 178),
+            (Theme::Colorblind, Role::Time | Role::Good) => rgb(86, 180, 233),
+            (Theme::Colorblind, Role::EventType) => rgb(230, 159, 0),
+            (Theme::Colorblind, Role::Location) => rgb(0, 158, 115),
+            (Theme::Colorblind, Role::Lecturer) => rgb(204, 121, 167),
+            (Theme::Colorblind, Role::Warning) => rgb(240, 228, 66),
+            (Theme::Colorblind, Role::Problem) => rgb(213, 94, 0),
+        }
+    }
+
+    /// The colour from the sixteen basic ones, for terminals without 24-bit colour.
+    fn basic_colour(self, role: Role) -> colored::Color {
+        match (self, role) {
+            (Theme::Default, role) => self.colour(role),
+            (Theme::Colorblind, Role::Heading) => colored::Color::BrightBlue,
+            (Theme::Colorblind, Role::Time | Role::Good) => colored::Color::BrightCyan,
+            (Theme::Colorblind, Role::EventType) => colored::Color::Yellow,
+            (Theme::Colorblind, Role::Location) => colored::Color::Cyan,
+            (Theme::Colorblind, Role::Lecturer) => colored::Color::Magenta,
+            (Theme::Colorblind, Role::Warning) => colored::Color::BrightYellow,
+            (Theme::Colorblind, Role::Problem) => colored::Color::BrightMagenta,
+        }
+    }
+}
+
+/// Whether the terminal says it shows 24-bit colour, as `colored` decides it.
+fn truecolor_support() -> bool {
+    matches!(env::var("COLORTERM").as_deref(), Ok("truecolor" | "24bit"))
+}
+
+fn paint(text: &str, role: Role) -> ColoredString {
+    let theme = THEME.get().copied().unwrap_or_default();
+    match theme.colour(role) {
+        // Left to itself, `colored` would round to the nearest basic colour, which can turn the
+        // colorblind palette's orange red and its bluish green green.
+        colored::Color::TrueColor { .. } if !truecolor_support() => text.color(theme.basic_colour(role)),
+        colour => text.color(colour),
+    }
+}
+
+/// A colour from the config: a terminal colour's name ("cyan", "bright_blue") or "#rrggbb".
+fn parse_cell_colour(name: &str) -> Option<Color> {
+    let name = name.trim().to_lowercase().replace(['-', ' '], "_");
+    if let Some(hex) = name.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb { r: channel(0)?, g: channel(2)?, b: channel(4)? });
+    }
+    Some(match name.as_str() {
+        "black" => Color::Black,
+        "red" => Color::DarkRed,
+        "green" => Color::DarkGreen,
+        "yellow" => Color::DarkYellow,
+        "blue" => Color::DarkBlue,
+        "magenta" => Color::DarkMagenta,
+        "cyan" => Color::DarkCyan,
+        "white" => Color::Grey,
+        "grey" | "gray" | "bright_black" => Color::DarkGrey,
+        "bright_red" => Color::Red,
+        "bright_green" => Color::Green,
+        "bright_yellow" => Color::Yellow,
+        "bright_blue" => Color::Blue,
+        "bright_magenta" => Color::Magenta,
+        "bright_cyan" => Color::Cyan,
+        "bright_white" => Color::White,
+        _ => return None,
+    })
+}
+
+/// The `[display.module_colors]` colour for the event's title. The longest matching unit wins,
+/// so that "Computational Physics" can differ from "Physics".
+fn module_colour(event: &Event, display: &DisplayConfig) -> Option<Color> {
+    display.module_colors.iter()
+        .filter(|(unit, _)| title_matches_unit(&event.title, unit))
+        .max_by_key(|(unit, _)| unit.len())
+        .and_then(|(_, colour)| parse_cell_colour(colour))
+}
+
+/// The colours `[display] color_by` shares out, in order.
+const LOCATION_PALETTE: [Color; 8] = [
+    Color::Cyan, Color::Magenta, Color::Yellow, Color::Green, Color::Blue, Color::Red, Color::DarkCyan, Color::DarkYellow,
+];
+
+/// Which of the first `palette_size` colours a location's building gets. This goes through
+/// `stable_hash` rather than the std hasher, whose seed changes between runs, so a building
+/// keeps its colour from run to run and machine to machine.
+fn location_colour_index(location: &str, palette_size: usize) -> usize {
+    let building = location.split(':').next().unwrap_or_default().trim().to_lowercase();
+    (stable_hash(&building) % palette_size.clamp(1, LOCATION_PALETTE.len()) as u64) as usize
+}
+
+/// The same colour as `paint`, for table cells.
+fn cell_colour(role: Role) -> Color {
+    match THEME.get().copied().unwrap_or_default().colour(role) {
+        colored::Color::TrueColor { r, g, b } => Color::Rgb { r, g, b },
+        colored::Color::Magenta => Color::Magenta,
+        colored::Color::Cyan => Color::Cyan,
+        colored::Color::Yellow => Color::Yellow,
+        colored::Color::Green => Color::Green,
+        colored::Color::Blue => Color::Blue,
+        colored::Color::Red => Color::Red,
+        _ => Color::Reset,
+    }
+}
+
+/// A muted background per weekday, so the week view's columns are easy to tell apart.
+fn day_header_background(date: NaiveDate) -> Color {
+    const BACKGROUNDS: [(u8, u8, u8); 7] = [
+        (49, 50, 68), (40, 56, 48), (62, 46, 40), (40, 52, 72), (60, 40, 62), (58, 56, 38), (38, 58, 62),
+    ];
+    let (r, g, b) = BACKGROUNDS[date.weekday().num_days_from_monday() as usize];
+    Color::Rgb { r, g, b }
+}
+
+/// The marker for today's column or line: an arrow when colour is on, plain text otherwise.
+fn today_marker() -> &'static str {
+    if colored::control::SHOULD_COLORIZE.should_colorize() && !ascii_only() { "◀ today" } else { "(today)" }
+}
+
+/// Whether to draw with ASCII only, decided once the config is loaded.
+static ASCII: OnceLock<bool> = OnceLock::new();
+
+fn ascii_only() -> bool {
+    ASCII.get().copied().unwrap_or(false)
+}
+
+/// Whether the locale says the terminal takes UTF-8, going by the first of LC_ALL, LC_CTYPE and
+/// LANG that is set, as the C library does. With none set, it's assumed to.
+fn locale_is_utf8(lc_all: Option<&str>, lc_ctype: Option<&str>, lang: Option<&str>) -> bool {
+    match [lc_all, lc_ctype, lang].into_iter().flatten().find(|value| !value.is_empty()) {
+        Some(locale) => {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        }
+        None => true,
+    }
+}
+
+/// ASCII-only drawing as `[display] ascii` (or --ascii/--unicode) says, else whenever the
+/// locale doesn't take UTF-8.
+fn use_ascii(configured: Option<bool>, lc_all: Option<&str>, lc_ctype: Option<&str>, lang: Option<&str>) -> bool {
+    configured.unwrap_or_else(|| !locale_is_utf8(lc_all, lc_ctype, lang))
+}
+
+/// Rounded box drawing, or plain ASCII borders where that wouldn't render.
+fn load_table_preset(table: &mut Table) -> &mut Table {
+    // Tables are coloured whenever the rest of the output is, as with CLICOLOR_FORCE when piped.
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        table.enforce_styling();
+    }
+    if ascii_only() { table.load_preset(ASCII_FULL) } else { table.load_preset(UTF8_FULL).apply_modifier(UTF8_ROUND_CORNERS) }
+}
+
+/// The width to lay tables out to: `--width`, else the terminal's, falling back to 80 where it
+/// can't be told. Piped output gets 200 columns, so that lines aren't wrapped for a terminal
+/// that isn't there.
+fn table_width(requested: Option<u16>) -> u16 {
+    requested.unwrap_or_else(|| {
+        if std::io::stdout().is_terminal() { Table::new().width().unwrap_or(80) } else { 200 }
+    })
+}
+
+/// The mark for a finished fetch: ✓ or ✗, or OK or FAIL in ASCII.
+fn outcome_mark(ok: bool) -> ColoredString {
+    match (ok, ascii_only()) {
+        (true, false) => paint("✓", Role::Good),
+        (true, true) => paint("OK", Role::Good),
+        (false, false) => paint("✗", Role::Problem),
+        (false, true) => paint("FAIL", Role::Problem),
+    }
+}
+
+// --- Core Logic ---
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static LOG_FILE: OnceLock<PathBuf> = OnceLock::new();
+/// The configured API's address, for links into its app.
+static CAMPUSM_BASE_URL: OnceLock<String> = OnceLock::new();
+
+/// Writes a diagnostic line to stderr, or to `[log] file` when stderr isn't a terminal (under
+/// cron, say) and one is configured.
+fn log_line(line: &str) {
+    if let Some(path) = LOG_FILE.get().filter(|_| !std::io::stderr().is_terminal()) {
+        let stamped = format!("{} {}\n", Local::now().to_rfc3339(), strip_ansi(line));
+        let written = fs::OpenOptions::new().create(true).append(true).open(path).and_then(|mut file| file.write_all(stamped.as_bytes()));
+        if written.is_ok() { return; }
+    }
+    eprintln!("{}", line);
+}
+
+fn log_warning(message: impl std::fmt::Display) {
+    log_line(&format!("{} {}", paint("Warning:", Role::Warning), message));
+}
+
+/// Prints diagnostic detail when `--verbose` is on.
+fn log_verbose(message: impl std::fmt::Display) {
+    if VERBOSE.load(Ordering::Relaxed) {
+        log_line(&format!("{} {}", "[verbose]".dimmed(), message));
+    }
+}
+
+/// The headers sent with every API request: the source's own, then the user's overrides.
+fn request_headers(api: &ApiConfig, source: &dyn TimetableSource) -> Result<HeaderMap, Box<dyn Error + Send + Sync>> {
+    let mut headers = source.headers()?;
+    headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+    headers.insert(header::ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.5"));
+    headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+    let user_agent = api.user_agent.as_deref().unwrap_or(USER_AGENT);
+    let user_agent = HeaderValue::from_str(user_agent).map_err(|_| format!("Invalid [api] user_agent: {:?}.", user_agent))?;
+    headers.insert(header::USER_AGENT, user_agent);
+
+    for (name, value) in &api.extra_headers {
+        let header_name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name {:?} in [api] extra_headers.", name))?;
+        if header_name == header::COOKIE {
+            return Err("The cookie can't be set through [api] extra_headers; use [api] cookie instead.".into());
+        }
+        let header_value = HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header {:?} in [api] extra_headers.", name))?;
+        headers.insert(header_name, header_value);
+    }
+
+    let cookie = HeaderValue::from_str(&api.cookie).map_err(|_| "Invalid [api] cookie: it contains characters that can't be sent in a header.")?;
+    headers.insert(header::COOKIE, cookie);
+    Ok(headers)
+}
+
+// --- Cookie Jar ---
+
+/// Expands a leading `~` to the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), directories::BaseDirs::new()) {
+        (Ok(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// The saved cookie jar, or an empty one if there isn't one yet or it can't be read.
+fn load_cookie_jar(path: &Path) -> reqwest_cookie_store::CookieStoreMutex {
+    let store = match fs::File::open(path) {
+        Ok(file) => cookie_store::serde::json::load(std::io::BufReader::new(file)).unwrap_or_else(|e| {
+            log_warning(format!("Ignoring the unreadable cookie jar '{}': {}", path.display(), e));
+            cookie_store::CookieStore::default()
+        }),
+        Err(_) => cookie_store::CookieStore::default(),
+    };
+    reqwest_cookie_store::CookieStoreMutex::new(store)
+}
+
+/// Saves the jar's persistent cookies; session cookies and expired ones are left out.
+fn save_cookie_jar(path: &Path, jar: &reqwest_cookie_store::CookieStoreMutex) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::File::create(path)?;
+    let store = jar.lock().map_err(|_| "The cookie jar was poisoned.")?;
+    cookie_store::serde::json::save(&store, &mut file).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// `cookie` with the jar's cookies for `url` added. reqwest only sends the jar when no Cookie
+/// header is set, and the configured cookie always is. The configured cookie wins a clash.
+fn with_jar_cookies(cookie: &str, jar: &reqwest_cookie_store::CookieStoreMutex, url: &str) -> String {
+    let (Ok(url), Ok(store)) = (reqwest::Url::parse(url), jar.lock()) else { return cookie.to_string() };
+    let configured: Vec<&str> = cookie.split(';').filter_map(|pair| pair.split_once('=')).map(|(name, _)| name.trim()).collect();
+    let mut pairs = vec![cookie.to_string()];
+    pairs.extend(store.get_request_values(&url)
+        .filter(|(name, _)| !configured.contains(name))
+        .map(|(name, value)| format!("{}={}", name, value)));
+    pairs.join("; ")
+}
+
+// --- Timetable Sources ---
+
+/// A university's timetable API: where to ask, what it needs sent, and how to read its answer.
+trait TimetableSource {
+    /// The URL for the events between `start` and `end`.
+    fn build_url(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> String;
+
+    /// Headers the API expects besides the common ones and the cookie.
+    fn headers(&self) -> Result<HeaderMap, Box<dyn Error + Send + Sync>>;
+
+    /// Reads one event from the response.
+    fn map_event(&self, value: serde_json::Value) -> Result<Event, serde_json::Error>;
+
+    /// Reads the events one by one, so that a single malformed event doesn't lose the rest.
+    fn parse_events(&self, body: &str) -> Result<(Vec<Event>, MalformedEvents), serde_json::Error> {
+        let data: RawApiResponse = serde_json::from_str(body)?;
+        let mut events = Vec::with_capacity(data.events.len());
+        let mut malformed = Vec::new();
+        for (index, value) in data.events.into_iter().enumerate() {
+            match self.map_event(value) {
+                Ok(event) => events.push(event.composed()),
+                Err(e) => malformed.push((index, e)),
+            }
+        }
+        Ok((events, malformed))
+    }
+}
+
+/// Our name for each event field, and Bristol's, which `Event` is deserialised with.
+const EVENT_FIELDS: &[(&str, &str)] = &[
+    ("title", "desc1"), ("type", "desc2"), ("start", "start"), ("end", "end"),
+    ("location", "locAdd1"), ("lecturer", "teacherName"), ("id", "id"), ("level", "level"),
+];
+
+fn campusm_url(base_url: &str, path: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let format = "%Y-%m-%dT%H:%M:%S.000Z";
+    format!("{}{}?start={}&end={}", base_url, path, start.format(format), end.format(format))
+}
+
+/// CampusM serves the timetable to its own web app, so look like it.
+fn campusm_headers(base_url: &str) -> Result<HeaderMap, Box<dyn Error + Send + Sync>> {
+    let mut headers = HeaderMap::new();
+    let referer = format!("{}/campusm/home", base_url);
+    headers.insert(header::REFERER, HeaderValue::from_str(&referer).map_err(|_| format!("Invalid [api] base_url: {:?}.", base_url))?);
+    headers.insert("X-Requested-With", HeaderValue::from_static("XMLHttpRequest"));
+    Ok(headers)
+}
+
+struct Bristol {
+    base_url: String,
+}
+
+impl TimetableSource for Bristol {
+    fn build_url(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+        campusm_url(&self.base_url, CAMPUSM_TIMETABLE_PATH, start, end)
+    }
+
+    fn headers(&self) -> Result<HeaderMap, Box<dyn Error + Send + Sync>> {
+        campusm_headers(&self.base_url)
+    }
+
+    fn map_event(&self, value: serde_json::Value) -> Result<Event, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+}
+
+/// A CampusM deployment whose path and field names differ from Bristol's.
+struct GenericCampusm {
+    base_url: String,
+    path: String,
+    /// Bristol's name for a field, keyed by this feed's.
+    renames: Vec<(String, &'static str)>,
+}
+
+impl TimetableSource for GenericCampusm {
+    fn build_url(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+        campusm_url(&self.base_url, &self.path, start, end)
+    }
+
+    fn headers(&self) -> Result<HeaderMap, Box<dyn Error + Send + Sync>> {
+        campusm_headers(&self.base_url)
+    }
+
+    fn map_event(&self, mut value: serde_json::Value) -> Result<Event, serde_json::Error> {
+        if let Some(fields) = value.as_object_mut() {
+            for (theirs, bristol) in &self.renames {
+                if let Some(field) = fields.remove(theirs) {
+                    fields.insert(bristol.to_string(), field);
+                }
+            }
+        }
+        serde_json::from_value(value)
+    }
+}
+
+/// The configured provider, checked for settings that are missing or don't apply to it.
+fn timetable_source(api: &ApiConfig) -> Result<Box<dyn TimetableSource>, Box<dyn Error + Send + Sync>> {
+    let base_url = api.base_url.as_deref().map(|url| url.trim_end_matches('/').to_string());
+    match api.provider {
+        Provider::Bristol => {
+            if api.path.is_some() || !api.fields.is_empty() {
+                return Err("[api] path and fields only apply to provider = \"generic_campusm\".".into());
+            }
+            Ok(Box::new(Bristol { base_url: base_url.unwrap_or_else(|| BRISTOL_BASE_URL.to_string()) }))
+        }
+        Provider::GenericCampusm => {
+            let base_url = base_url.ok_or("[api] base_url is required for provider = \"generic_campusm\".")?;
+            let renames = api.fields.iter().map(|(ours, theirs)| {
+                let (_, bristol) = EVENT_FIELDS.iter().find(|(name, _)| name == ours).ok_or_else(|| {
+                    let names: Vec<&str> = EVENT_FIELDS.iter().map(|(name, _)| *name).collect();
+                    format!("Unknown event field {:?} in [api] fields; expected one of {}.", ours, names.join(", "))
+                })?;
+                Ok((theirs.clone(), *bristol))
+            }).collect::<Result<_, String>>()?;
+            let path = api.path.clone().unwrap_or_else(|| CAMPUSM_TIMETABLE_PATH.to_string());
+            Ok(Box::new(GenericCampusm { base_url, path, renames }))
+        }
+    }
+}
+
+// --- Fetch Reporting ---
+
+/// A failed request, with a short reason for summaries alongside the full detail.
+#[derive(Debug)]
+struct FetchError {
+    reason: String,
+    detail: String,
+    /// The HTTP status, when a response came back at all.
+    status: Option<u16>,
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.detail)
+    }
+}
+
+impl Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        let reason = if e.is_timeout() {
+            "timeout"
+        } else if e.is_connect() {
+            "connection failed"
+        } else if e.is_decode() {
+            "unreadable response"
+        } else {
+            "request failed"
+        };
+        FetchError { reason: reason.to_string(), detail: e.to_string(), status: e.status().map(|status| status.as_u16()) }
+    }
+}
+
+#[derive(Debug)]
+struct SourceOutcome {
+    name: String,
+    failure: Option<String>,
+}
+
+/// What happened to each request that contributed to the merged events.
+#[derive(Debug, Default)]
+struct FetchReport {
+    sources: Vec<SourceOutcome>,
+    /// Events dropped because their start or end time couldn't be parsed.
+    unparseable: Vec<Event>,
+    /// Events that couldn't be read at all, as "<source> #<index>: <error>".
+    malformed: Vec<String>,
+    /// Events returned twice, by the requests for chunks either side of them.
+    duplicates: usize,
+    /// When the events came from the API, where known.
+    fetched_at: Option<DateTime<Utc>>,
+    window_start: DateTime<Utc>,
+    window_end: DateTime<Utc>,
+    /// Set when the API couldn't be reached and these are the cached events from this time.
+    cached_at: Option<DateTime<Utc>>,
+}
+
+impl FetchReport {
+    fn covers(&self, date: NaiveDate) -> bool {
+        let start = to_display(&self.window_start).date_naive();
+        let end = to_display(&self.window_end).date_naive();
+        start <= date && date <= end
+    }
+
+    fn is_partial(&self) -> bool {
+        self.cached_at.is_some() || self.sources.iter().any(|source| source.failure.is_some())
+    }
+
+    /// How out of date the events are, when they came from the cache because the API couldn't
+    /// be reached.
+    fn staleness(&self) -> Option<(String, Severity)> {
+        self.cached_at.and_then(|cached_at| staleness_label(Utc::now() - cached_at))
+    }
+
+    /// A one-line summary of the failed sources, if there were any.
+    fn warning(&self) -> Option<String> {
+        if let Some(cached_at) = self.cached_at {
+            let age = self.staleness().map(|(label, _)| format!(" ({})", label)).unwrap_or_default();
+            return Some(format!("⚠ Couldn't reach the timetable; showing data fetched {}{}", to_display(&cached_at).format("%a %d %b %H:%M"), age));
+        }
+        let failed: Vec<String> = self.sources.iter()
+            .filter_map(|source| source.failure.as_ref().map(|reason| format!("{} ({})", source.name, reason)))
+            .collect();
+        if failed.is_empty() { return None; }
+        Some(format!("⚠ {} of {} sources failed: {} — data may be incomplete", failed.len(), self.sources.len(), failed.join(", ")))
+    }
+}
+
+/// `[cache] stale_warning_hours` and `stale_alert_hours`.
+static STALE_HOURS: OnceLock<(i64, i64)> = OnceLock::new();
+
+/// How worrying out-of-date data is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    Notice,
+    Warning,
+    Alert,
+}
+
+impl Severity {
+    fn paint(self, text: &str) -> ColoredString {
+        match self {
+            Severity::Notice => text.normal(),
+            Severity::Warning => paint(text, Role::Warning),
+            Severity::Alert => paint(text, Role::Problem).bold(),
+        }
+    }
+}
+
+/// "45m" or "26h".
+fn format_age(age: Duration) -> String {
+    if age < Duration::hours(1) { format!("{}m", age.num_minutes()) } else { format!("{}h", age.num_hours()) }
+}
+
+/// Describes data fetched `age` ago ("data is 26h old"), with how much that matters under the
+/// configured thresholds. Data from the future, which a wrong clock can give, isn't described.
+fn staleness_label(age: Duration) -> Option<(String, Severity)> {
+    if age < Duration::zero() {
+        return None;
+    }
+    let (warning_hours, alert_hours) = STALE_HOURS.get().copied().unwrap_or((default_stale_warning_hours(), default_stale_alert_hours()));
+    let severity = if age > Duration::hours(alert_hours) {
+        Severity::Alert
+    } else if age > Duration::hours(warning_hours) {
+        Severity::Warning
+    } else {
+        Severity::Notice
+    };
+    Some((format!("data is {} old", format_age(age)), severity))
+}
+
+/// The suffix status bars add when the data is incomplete: `?`, or the age (`!26h`) once cached
+/// data is past the alert threshold.
+fn stale_marker(report: &FetchReport) -> String {
+    match (report.cached_at, report.staleness()) {
+        (Some(cached_at), Some((_, Severity::Alert))) => format!("!{}", format_age(Utc::now() - cached_at)),
+        _ if report.is_partial() => "?".to_string(),
+        _ => String::new(),
+    }
+}
+
+// --- Fetch History ---
+
+/// One request to the API, as a line of the fetch history.
+#[derive(Serialize, Deserialize, Debug)]
+struct FetchAttempt {
+    at: DateTime<Utc>,
+    duration_ms: u64,
+    /// "ok", or the failure's reason, such as "timeout" or "HTTP 503".
+    outcome: String,
+    status: Option<u16>,
+}
+
+fn load_fetch_history() -> Vec<FetchAttempt> {
+    fs::read_to_string(data_dir().join(FETCH_HISTORY_FILE)).unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Adds the attempts to the history, keeping only the latest `limit`. This is best effort: a
+/// history that can't be written is noted under --verbose and otherwise ignored.
+fn record_fetch_history(attempts: Vec<FetchAttempt>, limit: usize) {
+    let mut history = load_fetch_history();
+    history.extend(attempts);
+    let history = &history[history.len().saturating_sub(limit)..];
+    let written = (|| -> Result<(), Box<dyn Error + Send + Sync>> {
+        let dir = data_dir();
+        fs::create_dir_all(&dir)?;
+        let mut lines = String::new();
+        for attempt in history {
+            lines.push_str(&serde_json::to_string(attempt)?);
+            lines.push('\n');
+        }
+        write_atomically(&dir.join(FETCH_HISTORY_FILE), lines.as_bytes())?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        log_verbose(format!("Could not record the fetch history: {}", e));
+    }
+}
+
+/// The `p`th percentile of the sorted durations, by the nearest-rank method.
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// "1.9s" or "420ms".
+fn format_millis(ms: u64) -> String {
+    if ms >= 1000 { format!("{:.1}s", ms as f64 / 1000.0) } else { format!("{}ms", ms) }
+}
+
+fn api_stats(config: &Config) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !config.telemetry.local_history {
+        eprintln!("{} Fetch history is off ([telemetry] local_history = false); showing what was recorded before.", paint("Note:", Role::Warning));
+    }
+    let history = load_fetch_history();
+    if history.is_empty() {
+        eprintln!("No requests have been recorded yet.");
+        return Ok(());
+    }
+    let now = Utc::now();
+    for (label, period) in [("Last 24 hours", Duration::days(1)), ("Last 7 days", Duration::days(7))] {
+        let recent: Vec<&FetchAttempt> = history.iter().filter(|attempt| now - attempt.at <= period).collect();
+        if recent.is_empty() {
+            println!("{:<15}no requests", format!("{}:", label));
+            continue;
+        }
+        let succeeded = recent.iter().filter(|attempt| attempt.outcome == "ok").count();
+        let mut durations: Vec<u64> = recent.iter().map(|attempt| attempt.duration_ms).collect();
+        durations.sort_unstable();
+        println!(
+            "{:<15}{} requests, {:.1}% succeeded, p50 {}, p95 {}",
+            format!("{}:", label),
+            recent.len(),
+            succeeded as f64 * 100.0 / recent.len() as f64,
+            format_millis(percentile(&durations, 50.0)),
+            format_millis(percentile(&durations, 95.0)),
+        );
+    }
+    let failures: Vec<&FetchAttempt> = history.iter().filter(|attempt| attempt.outcome != "ok" && now - attempt.at <= Duration::days(7)).collect();
+    if !failures.is_empty() {
+        println!("\n{}", "Recent failures:".bold());
+        for attempt in failures.iter().rev().take(10) {
+            let at = to_display(&attempt.at);
+            println!("  {} {}  {} after {}", at.format("%a %d %b"), format_time(at, &config.display), paint(&attempt.outcome, Role::Problem), format_millis(attempt.duration_ms));
+        }
+    }
+    Ok(())
+}
+
+// --- fetch_events (MODIFIED WITH BETTER ERROR HANDLING) ---
+/// The position in the response and the error of each event that couldn't be read.
+type MalformedEvents = Vec<(usize, serde_json::Error)>;
+
+/// One window's events, those that couldn't be read, and the HTTP status.
+type FetchedWindow = (Vec<Event>, MalformedEvents, u16);
+
+fn fetch_window(client: &reqwest::blocking::Client, source: &dyn TimetableSource, headers: &HeaderMap, jar: Option<&reqwest_cookie_store::CookieStoreMutex>, start: DateTime<Utc>, end: DateTime<Utc>) -> Result<FetchedWindow, FetchError> {
+    let url = source.build_url(start, end);
+    let mut headers = headers.clone();
+    if let (Some(jar), Some(cookie)) = (jar, headers.get(header::COOKIE).and_then(|cookie| cookie.to_str().ok())) {
+        if let Ok(cookie) = HeaderValue::from_str(&with_jar_cookies(cookie, jar, &url)) {
+            headers.insert(header::COOKIE, cookie);
+        }
+    }
+    let response = client.get(url).headers(headers).send()?;
+    
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().unwrap_or_else(|_| "Could not read response body".to_string());
+        return Err(FetchError {
+            reason: format!("HTTP {}", status.as_u16()),
+            detail: format!("API request failed with status: {}. Server response:\n{}", status, body),
+            status: Some(status.as_u16()),
+        });
+    }
+
+    // IMPROVED ERROR HANDLING: Read body as text first, then attempt to parse.
+    // This allows us to include the problematic body in the error message.
+    let body_text = response.text()?;
+    source.parse_events(&body_text)
+        .map(|(events, malformed)| (events, malformed, status.as_u16()))
+        .map_err(|e| FetchError {
+            reason: "invalid JSON".to_string(),
+            detail: format!(
+                "Failed to decode JSON response from server. Error: {}\n\n---\nReceived Body:\n{}---",
+                e, body_text
+            ),
+            status: Some(status.as_u16()),
+        })
+}
+
+/// Fetches the whole window, split into `chunk_days` requests when configured. Failed chunks are
+/// recorded in the report; only a fetch where every chunk failed is an error.
+fn fetch_events(config: &Config) -> Result<(ApiResponse, FetchReport), Box<dyn Error + Send + Sync>> {
+    let today = Utc::now();
+    fetch_range(config, today - Duration::days(config.api.fetch_days_past), today + Duration::days(config.api.fetch_days_future))
+}
+
+fn fetch_range(config: &Config, window_start: DateTime<Utc>, window_end: DateTime<Utc>) -> Result<(ApiResponse, FetchReport), Box<dyn Error + Send + Sync>> {
+    let chunk = config.api.chunk_days.filter(|&days| days > 0).map(Duration::days).unwrap_or(window_end - window_start);
+
+    let jar_path = config.api.cookie_jar_file.as_deref().map(expand_home);
+    let jar = jar_path.as_deref().map(|path| Arc::new(load_cookie_jar(path)));
+    let client = match &jar {
+        Some(jar) => reqwest::blocking::Client::builder().cookie_provider(Arc::clone(jar)).build()?,
+        None => reqwest::blocking::Client::new(),
+    };
+    let source = timetable_source(&config.api)?;
+    let headers = request_headers(&config.api, source.as_ref())?;
+    let mut report = FetchReport { window_start, window_end, fetched_at: Some(Utc::now()), ..Default::default() };
+    let mut events: Vec<Event> = Vec::new();
+    let mut first_error = None;
+    let mut attempts = Vec::new();
+    let mut chunk_start = window_start;
+    while chunk_start < window_end {
+        let chunk_end = (chunk_start + chunk).min(window_end);
+        let name = format!("{}..{}", chunk_start.format("%Y-%m-%d"), chunk_end.format("%Y-%m-%d"));
+        let started = std::time::Instant::now();
+        let fetched = fetch_window(&client, source.as_ref(), &headers, jar.as_deref(), chunk_start, chunk_end);
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match fetched {
+            Ok((chunk_events, malformed, status)) => {
+                attempts.push(FetchAttempt { at: Utc::now(), duration_ms, outcome: "ok".to_string(), status: Some(status) });
+                events.extend(chunk_events);
+                report.malformed.extend(malformed.into_iter().map(|(index, e)| format!("{} #{}: {}", name, index, e)));
+                report.sources.push(SourceOutcome { name, failure: None });
+            }
+            Err(e) => {
+                attempts.push(FetchAttempt { at: Utc::now(), duration_ms, outcome: e.reason.clone(), status: e.status });
+                report.sources.push(SourceOutcome { name, failure: Some(e.reason.clone()) });
+                first_error.get_or_insert(e);
+            }
+        }
+        chunk_start = chunk_end;
+    }
+    if config.telemetry.local_history {
+        record_fetch_history(attempts, config.telemetry.history_entries);
+    }
+    if let (Some(path), Some(jar)) = (&jar_path, &jar) {
+        if let Err(e) = save_cookie_jar(path, jar) {
+            log_warning(format!("Could not save the cookie jar to '{}': {}", path.display(), e));
+        }
+    }
+
+    if let Some(e) = first_error.filter(|_| report.sources.iter().all(|source| source.failure.is_some())) {
+        return Err(e.into());
+    }
+    // Before anything matches or hashes the text, so that ids and rules see the tidied version.
+    if !config.api.raw_text {
+        normalise_event_text(&mut events);
+    }
+    // Events overlapping a chunk boundary are returned by both requests.
+    let mut seen = std::collections::HashSet::new();
+    let received = events.len();
+    events.retain(|event| seen.insert(event_id(event)));
+    report.duplicates = received - events.len();
+    normalise_timestamps(&mut events);
+    // Everything downstream relies on the times parsing, so set aside the events whose don't.
+    let (events, unparseable) = events.into_iter().partition(|event| {
+        parse_timestamp(&event.start).is_some() && parse_timestamp(&event.end).is_some()
+    });
+    report.unparseable = unparseable;
+    let mut events: Vec<Event> = events;
+    let offsets = non_utc_offsets(&events);
+    if !offsets.is_empty() {
+        log_verbose(format!("The API sent times with non-UTC offsets ({}); they may have been converted twice.", offsets.join(", ")));
+    }
+    validate_events(&mut events, Duration::hours(config.display.suspicious_duration_hours));
+    fill_blank_titles(&mut events);
+    if config.api.strict {
+        let invalid: Vec<String> = events.iter()
+            .filter_map(|event| event.issue.map(|issue| format!("{} at {} ({})", event.title, event.start, issue.describe())))
+            .collect();
+        if !invalid.is_empty() {
+            return Err(format!("The timetable contains events with implausible times:\n  {}", invalid.join("\n  ")).into());
+        }
+    }
+    Ok((ApiResponse { events }, report))
+}
+
+/// Rewrites times the API sent in a format other than RFC 3339 into it, so that the cache and
+/// exports stay consistent, noting under --verbose which formats turned up.
+fn normalise_timestamps(events: &mut [Event]) {
+    let mut drifted: BTreeMap<&str, usize> = BTreeMap::new();
+    for event in events.iter_mut() {
+        for time in [&mut event.start, &mut event.end] {
+            if let Some((parsed, format)) = parse_timestamp_format(time).filter(|&(_, format)| format != "RFC 3339") {
+                *time = parsed.to_rfc3339();
+                *drifted.entry(format).or_default() += 1;
+            }
+        }
+    }
+    for (format, count) in drifted {
+        log_verbose(format!("Read {} times from the API in a fallback format ({}).", count, format));
+    }
+}
+
+/// The distinct UTC offsets other than zero in the events' times. The API has only ever sent
+/// UTC, so anything else suggests its assumptions have changed.
+fn non_utc_offsets(events: &[Event]) -> Vec<String> {
+    let mut offsets: Vec<String> = events.iter()
+        .flat_map(|event| [&event.start, &event.end])
+        .filter_map(|time| parse_timestamp(time))
+        .map(|time| *time.offset())
+        .filter(|offset| offset.local_minus_utc() != 0)
+        .map(|offset| offset.to_string())
+        .collect();
+    offsets.sort();
+    offsets.dedup();
+    offsets
+}
+
+/// Text that was UTF-8 read as Windows-1252 somewhere upstream, and what it should have been.
+const MOJIBAKE: &[(&str, &str)] = &[
+    ("â€“", "–"), ("â€”", "—"), ("â€˜", "‘"), ("â€™", "’"), ("â€œ", "“"), ("â€\u{9d}", "”"), ("â€¦", "…"), ("Â\u{a0}", " "),
+];
+
+/// Decodes the HTML entities the API's text sometimes contains, named (only the common ones)
+/// and numeric. Anything that isn't a recognised entity is left as it is.
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest[1..].find(';').filter(|&end| end <= 10).map(|end| &rest[1..end + 1]);
+        let decoded = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            "ndash" => Some('–'),
+            "mdash" => Some('—'),
+            _ => entity.strip_prefix('#')
+                .and_then(|number| match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => number.parse().ok(),
+                })
+                .and_then(char::from_u32),
+        });
+        match (entity, decoded) {
+            (Some(entity), Some(decoded)) => {
+                out.push(decoded);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Tidies text from the API: entities decoded, mis-decoded punctuation repaired, and runs of
+/// whitespace collapsed to a single space with none at either end. Unicode normalisation has
+/// already happened, in `Event::composed`.
+fn normalise_text(text: &str) -> String {
+    let mut text = decode_entities(text);
+    for (broken, fixed) in MOJIBAKE {
+        if text.contains(broken) {
+            text = text.replace(broken, fixed);
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Tidies a comma-separated list of lecturers: each name trimmed and capitalised word by word,
+/// empty and repeated names dropped, and the rest joined with ", ".
+fn normalise_lecturer_name(s: &str) -> String {
+    let capitalise = |word: &str| {
+        let mut chars = word.chars();
+        chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+    };
+    let mut names: Vec<String> = Vec::new();
+    for name in s.trim().split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        let name = name.split_whitespace().map(capitalise).collect::<Vec<_>>().join(" ");
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.join(", ")
+}
+
+/// Tidies a location: single spaces, ": " between its parts, no "Rear:" or "Front:" labels and
+/// no punctuation at the end, so "Physics Building:  Rear: 3.21." becomes "Physics Building: 3.21".
+/// Only a colon before a space or the end separates parts, which leaves links alone.
+fn normalise_location(s: &str) -> String {
+    let collapsed = s.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut parts = Vec::new();
+    let mut rest = collapsed.as_str();
+    while let Some(colon) = rest.char_indices().find(|&(i, c)| c == ':' && rest[i + 1..].chars().next().is_none_or(char::is_whitespace)).map(|(i, _)| i) {
+        parts.push(rest[..colon].trim());
+        rest = &rest[colon + 1..];
+    }
+    parts.push(rest.trim());
+    let last = parts.len() - 1;
+    let kept: Vec<&str> = parts.iter().enumerate()
+        .filter(|&(i, part)| !(part.is_empty() || (i < last && ["Rear", "Front"].contains(part))))
+        .map(|(_, part)| *part)
+        .collect();
+    kept.join(": ").trim_end_matches([':', ';', ',', '.', '-', ' ']).to_string()
+}
+
+/// Tidies the text fields of freshly fetched events, noting under --verbose how many changed.
+fn normalise_event_text(events: &mut [Event]) {
+    let mut changed = 0;
+    for event in events.iter_mut() {
+        let fields = [&mut event.title, &mut event.event_type, &mut event.location].into_iter().chain(event.teacher_name.as_mut());
+        for field in fields {
+            let tidied = normalise_text(field);
+            if tidied != *field {
+                *field = tidied;
+                changed += 1;
+            }
+        }
+        let location = normalise_location(&event.location);
+        if location != event.location {
+            event.location = location;
+            changed += 1;
+        }
+        if let Some(names) = &event.teacher_name {
+            let tidied = normalise_lecturer_name(names);
+            if tidied != *names {
+                event.teacher_name = Some(tidied).filter(|names| !names.is_empty());
+                changed += 1;
+            }
+        }
+    }
+    if changed > 0 {
+        log_verbose(format!("Tidied {} fields of the API's text (--raw keeps them as sent)", changed));
+    }
+}
+
+/// Titles events that came without one after their type, or "Untitled", so that no view shows
+/// a blank where the title should be.
+fn fill_blank_titles(events: &mut [Event]) {
+    for event in events.iter_mut().filter(|event| event.title.trim().is_empty()) {
+        let event_type = event.event_type.trim();
+        event.title = if event_type.is_empty() { "Untitled".to_string() } else { event_type.to_string() };
+    }
+}
+
+/// Flags events with implausible times, swapping the start and end of reversed ones so that
+/// durations are never negative. Events whose times don't parse are left for the caller.
+fn validate_events(events: &mut [Event], max_duration: Duration) {
+    for event in events {
+        let (Some(start), Some(end)) = (parse_timestamp(&event.start), parse_timestamp(&event.end)) else { continue };
+        if end < start {
+            std::mem::swap(&mut event.start, &mut event.end);
+            event.issue = Some(EventIssue::Reversed);
+        } else if end - start > max_duration {
+            event.issue = Some(EventIssue::TooLong);
+        }
+    }
+}
+
+// --- Event Identity & Last View ---
+
+/// FNV-1a. Unlike the std hasher, the result is guaranteed to be stable across runs and machines.
+fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+fn event_id(event: &Event) -> String {
+    format!("{:016x}", stable_hash(&format!("{}|{}|{}", event.title, event.start, event.end)))
+}
+
+fn data_dir() -> PathBuf {
+    // ProjectDirs falls back to the passwd entry when HOME is unset, as it can be under cron.
+    ProjectDirs::from("", "", "bstt")
+        .map(|dirs| dirs.data_dir().to_path_buf())
+        .unwrap_or_else(|| env::temp_dir().join("bstt"))
+}
+
+/// Describes everything that decides which rows a view contains and in what order.
+fn view_fingerprint(target_date: NaiveDate, options: &ViewOptions) -> String {
+    format!(
+        "date={};type={};search={};exclude={};min_duration={};pinned={};merge_adjacent={};reverse={};hide_finished={}",
+        target_date,
+        options.types.join(","),
+        options.search.as_deref().unwrap_or(""),
+        options.exclude.join(","),
+        options.min_duration,
+        options.display.pinned.join(","),
+        options.merge_adjacent,
+        options.reverse,
+        options.hide_finished,
+    )
+}
+
+fn save_last_view(fingerprint: String, events: &[Event]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    let view = LastView { created_at: display_now(), fingerprint, ids: events.iter().map(event_id).collect() };
+    write_atomically(&dir.join(LAST_VIEW_FILE), serde_json::to_string(&view)?.as_bytes())?;
+    Ok(())
+}
+
+fn load_last_view() -> Result<LastView, Box<dyn Error + Send + Sync>> {
+    let stale = "No recent numbered view found; please re-run `bstt --numbered` first.";
+    let contents = fs::read_to_string(data_dir().join(LAST_VIEW_FILE)).map_err(|_| stale)?;
+    let view: LastView = serde_json::from_str(&contents).map_err(|_| stale)?;
+    let now = display_now();
+    if now - view.created_at > Duration::minutes(LAST_VIEW_MAX_AGE_MINUTES) || view.created_at.date_naive() != now.date_naive() {
+        return Err(stale.into());
+    }
+    Ok(view)
+}
+
+// --- Event Cache ---
+
+/// The last successful fetch, kept so that an outage doesn't leave the bar empty.
+#[derive(Serialize, Deserialize, Debug)]
+struct EventCache {
+    /// Always first, so that it can be read before the rest of a binary cache.
+    schema_version: u32,
+    fetched_at: i64,
+    window_start: i64,
+    window_end: i64,
+    events: Vec<CachedEvent>,
+}
+
+/// `Event` as stored in the cache. The API's field names and its loosely typed id don't
+/// survive a format that isn't self-describing, so this is a plain copy.
+#[derive(Serialize, Deserialize, Debug)]
+struct CachedEvent {
+    title: String,
+    event_type: String,
+    start: String,
+    end: String,
+    location: String,
+    teacher_name: Option<String>,
+    booking_id: Option<String>,
+    level: Option<String>,
+    issue: Option<EventIssue>,
+    /// `event_hash` when it was fetched, for telling which events changed between fetches.
+    hash: String,
+}
+
+/// A hash of everything shown about the event, unlike `event_id`, which only covers what
+/// identifies it.
+fn event_hash(event: &Event) -> String {
+    let fields = [&event.title, &event.event_type, &event.start, &event.end, &event.location];
+    let optional = [&event.teacher_name, &event.booking_id, &event.level].map(|field| field.as_deref().unwrap_or(""));
+    format!("{:016x}", stable_hash(&fields.iter().map(|field| field.as_str()).chain(optional).collect::<Vec<_>>().join("|")))
+}
+
+impl From<&Event> for CachedEvent {
+    fn from(event: &Event) -> Self {
+        CachedEvent {
+            title: event.title.clone(),
+            event_type: event.event_type.clone(),
+            start: event.start.clone(),
+            end: event.end.clone(),
+            location: event.location.clone(),
+            teacher_name: event.teacher_name.clone(),
+            booking_id: event.booking_id.clone(),
+            level: event.level.clone(),
+            issue: event.issue,
+            hash: event_hash(event),
+        }
+    }
+}
+
+impl From<CachedEvent> for Event {
+    fn from(event: CachedEvent) -> Self {
+        Event {
+            title: event.title,
+            event_type: event.event_type,
+            start: event.start,
+            end: event.end,
+            location: event.location,
+            teacher_name: event.teacher_name,
+            booking_id: event.booking_id,
+            level: event.level,
+            issue: event.issue,
+        }.composed()
+    }
+}
+
+/// Replaces `path` with `contents` all at once, so that another bstt reading or writing it at the
+/// same time (watch mode, `serve`, a status bar) never sees half a file.
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    // Threads of one process (`serve`'s refresher and its requests) each need their own file.
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.{}.{}.tmp", name, std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+    let result = fs::File::create(&temp)
+        .and_then(|mut file| file.write_all(contents).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&temp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Moves an unreadable cache file aside, where it can still be looked at, so that it counts as a
+/// miss instead of failing every run until it's deleted.
+fn sideline_cache(path: &Path, problem: impl std::fmt::Display) -> Box<dyn Error + Send + Sync> {
+    let aside = path.with_extension(format!("{}.corrupt", path.extension().unwrap_or_default().to_string_lossy()));
+    let _ = fs::rename(path, &aside);
+    log_verbose(format!("The cache at '{}' is unreadable ({}); moved it to '{}'", path.display(), problem, aside.display()));
+    NO_CACHE.into()
+}
+
+fn cache_path(format: CacheFormat) -> PathBuf {
+    cache_path_in(&data_dir(), format)
+}
+
+fn cache_path_in(dir: &Path, format: CacheFormat) -> PathBuf {
+    dir.join(match format {
+        CacheFormat::Binary => CACHE_FILE_BINARY,
+        CacheFormat::Json => CACHE_FILE_JSON,
+    })
+}
+
+fn save_cache(format: CacheFormat, events: &[Event], report: &FetchReport) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    if VERBOSE.load(Ordering::Relaxed) {
+        if let Ok((previous, _)) = load_cache() {
+            let known: std::collections::HashSet<&str> = previous.events.iter().map(|event| event.hash.as_str()).collect();
+            let changed = events.iter().filter(|event| !known.contains(event_hash(event).as_str())).count();
+            log_verbose(format!("{} events are new or changed since the fetch at {}", changed, DateTime::from_timestamp(previous.fetched_at, 0).unwrap_or_default().to_rfc3339()));
+        }
+    }
+    let cache = EventCache {
+        schema_version: CACHE_SCHEMA_VERSION,
+        fetched_at: Utc::now().timestamp(),
+        window_start: report.window_start.timestamp(),
+        window_end: report.window_end.timestamp(),
+        events: events.iter().map(CachedEvent::from).collect(),
+    };
+    let contents = match format {
+        CacheFormat::Binary => [CACHE_MAGIC.as_slice(), &postcard::to_allocvec(&cache)?].concat(),
+        CacheFormat::Json => serde_json::to_vec_pretty(&cache)?,
+    };
+    write_atomically(&cache_path(format), &contents)?;
+    // Only one format is kept, so a stale file in the other can't be read by mistake.
+    let _ = fs::remove_file(cache_path(match format { CacheFormat::Binary => CacheFormat::Json, CacheFormat::Json => CacheFormat::Binary }));
+    Ok(())
+}
+
+fn load_cache() -> Result<(EventCache, PathBuf), Box<dyn Error + Send + Sync>> {
+    load_cache_in(&data_dir())
+}
+
+/// Reads whichever cache file exists in `dir`. A cache written by a version with a different
+/// schema counts as missing, as does a corrupt one, which is moved aside.
+fn load_cache_in(dir: &Path) -> Result<(EventCache, PathBuf), Box<dyn Error + Send + Sync>> {
+    let started = std::time::Instant::now();
+    let (cache, path) = match fs::read(cache_path_in(dir, CacheFormat::Binary)) {
+        Ok(bytes) => {
+            let path = cache_path_in(dir, CacheFormat::Binary);
+            let body = bytes.strip_prefix(CACHE_MAGIC.as_slice()).ok_or_else(|| sideline_cache(&path, "not a bstt cache"))?;
+            let (version, _) = postcard::take_from_bytes::<u32>(body).map_err(|e| sideline_cache(&path, e))?;
+            if version != CACHE_SCHEMA_VERSION {
+                return Err(format!("The cache was written with schema version {} (expected {}).", version, CACHE_SCHEMA_VERSION).into());
+            }
+            (postcard::from_bytes::<EventCache>(body).map_err(|e| sideline_cache(&path, e))?, path)
+        }
+        Err(_) => {
+            let path = cache_path_in(dir, CacheFormat::Json);
+            let contents = fs::read(&path).map_err(|_| NO_CACHE)?;
+            let cache: serde_json::Value = serde_json::from_slice(&contents).map_err(|e| sideline_cache(&path, e))?;
+            let version = cache["schema_version"].as_u64().unwrap_or_default();
+            if version != CACHE_SCHEMA_VERSION as u64 {
+                return Err(format!("The cache was written with schema version {} (expected {}).", version, CACHE_SCHEMA_VERSION).into());
+            }
+            (serde_json::from_value(cache).map_err(|e| sideline_cache(&path, e))?, path)
+        }
+    };
+    log_verbose(format!("Loaded {} cached events from '{}' in {:.2}ms", cache.events.len(), path.display(), started.elapsed().as_secs_f64() * 1000.0));
+    Ok((cache, path))
+}
+
+/// The cached events, if they were fetched within `[cache] ttl_minutes` and cover the window
+/// that would be fetched now. A `--strict` run always fetches, as the cache may hold events it
+/// would refuse.
+fn fresh_cached_events(config: &Config) -> Option<(ApiResponse, FetchReport)> {
+    let ttl = Duration::minutes(config.cache.ttl_minutes);
+    if ttl <= Duration::zero() || config.api.strict {
+        return None;
+    }
+    let (cache, _) = load_cache().ok()?;
+    let now = Utc::now();
+    let fetched_at = DateTime::from_timestamp(cache.fetched_at, 0)?;
+    let window_start = DateTime::from_timestamp(cache.window_start, 0)?;
+    let window_end = DateTime::from_timestamp(cache.window_end, 0)?;
+    // The window moves on with the clock, so the cached one may trail it by up to the TTL.
+    let wanted_start = now - Duration::days(config.api.fetch_days_past);
+    let wanted_end = now + Duration::days(config.api.fetch_days_future);
+    if now - fetched_at > ttl || window_start > wanted_start || window_end < wanted_end - ttl {
+        return None;
+    }
+    log_verbose(format!("Using the cache from {} minutes ago", (now - fetched_at).num_minutes()));
+    let report = FetchReport { window_start, window_end, fetched_at: Some(fetched_at), ..Default::default() };
+    Some((ApiResponse { events: cache.events.into_iter().map(Event::from).collect() }, report))
+}
+
+/// The cached events and a report describing them as such, for when the API can't be reached.
+fn cached_events() -> Result<(ApiResponse, FetchReport), Box<dyn Error + Send + Sync>> {
+    let (cache, _) = load_cache()?;
+    let timestamp = |seconds| DateTime::from_timestamp(seconds, 0).unwrap_or_default();
+    let report = FetchReport {
+        window_start: timestamp(cache.window_start),
+        window_end: timestamp(cache.window_end),
+        cached_at: Some(timestamp(cache.fetched_at)),
+        fetched_at: Some(timestamp(cache.fetched_at)),
+        ..Default::default()
+    };
+    Ok((ApiResponse { events: cache.events.into_iter().map(Event::from).collect() }, report))
+}
+
+fn cache_command(config: Config, action: CacheAction) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match action {
+        CacheAction::Status => {
+            let (cache, path) = load_cache()?;
+            let fetched_at = DateTime::from_timestamp(cache.fetched_at, 0).unwrap_or_default();
+            let age = Utc::now() - fetched_at;
+            println!("{}", path.display());
+            println!("{} events, fetched {} ({}h{:02}m ago)", cache.events.len(), to_display(&fetched_at).format("%Y-%m-%d %H:%M"), age.num_hours(), age.num_minutes() % 60);
+        }
+        CacheAction::Refresh => {
+            let mut config = config;
+            config.cache.ttl_minutes = 0;
+            let (events, _) = fetch_with_spinner(config, false)?;
+            eprintln!("Cached {} events.", events.events.len());
+        }
+        CacheAction::Clear => {
+            for format in [CacheFormat::Binary, CacheFormat::Json] {
+                match fs::remove_file(cache_path(format)) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// --- Calendar Import ---
+
+/// Undoes `ics_escape`.
+fn ics_unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(escaped) => out.push(escaped),
+            None => {}
+        }
+    }
+    out
+}
+
+/// A DTSTART or DTEND value as an instant. Times with a TZID are read in that zone, and
+/// floating times and all-day dates in the display timezone.
+fn ics_instant(property: &ical::property::Property) -> Option<DateTime<Utc>> {
+    let value = property.value.as_deref()?;
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return from_display(date.and_time(NaiveTime::MIN));
+    }
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok().map(|time| time.and_utc());
+    }
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    let tzid = property.params.iter().flatten().find(|(name, _)| name.eq_ignore_ascii_case("TZID")).and_then(|(_, values)| values.first());
+    match tzid {
+        Some(tzid) => tzid.parse::<Tz>().ok()?.from_local_datetime(&naive).earliest().map(|time| time.to_utc()),
+        None => from_display(naive),
+    }
+}
+
+/// Converts a VEVENT, or `None` if it lacks a title or a readable start.
+fn event_from_vevent(vevent: &ical::parser::ical::component::IcalEvent) -> Option<Event> {
+    let property = |name: &str| vevent.properties.iter().find(|property| property.name.eq_ignore_ascii_case(name));
+    let text = |name: &str| property(name).and_then(|property| property.value.as_deref()).map(ics_unescape);
+    let start = ics_instant(property("DTSTART")?)?;
+    let all_day = property("DTSTART")?.value.as_deref().is_some_and(|value| !value.contains('T'));
+    let end = property("DTEND").and_then(ics_instant)
+        .unwrap_or(if all_day { start + Duration::days(1) } else { start });
+    // Descriptions can run to paragraphs; the first line is plenty for the Type column.
+    let description = text("DESCRIPTION").unwrap_or_default();
+    let event_type = description.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or("Event");
+    let format = "%Y-%m-%dT%H:%M:%S.000Z";
+    Some(Event {
+        title: text("SUMMARY").filter(|title| !title.is_empty())?,
+        event_type: event_type.to_string(),
+        start: start.format(format).to_string(),
+        end: end.format(format).to_string(),
+        location: text("LOCATION").unwrap_or_default(),
+        teacher_name: None,
+        booking_id: None,
+        level: None,
+        issue: None,
+    }.composed())
+}
+
+/// The imported events, or none if nothing has been imported.
+fn imported_events() -> Result<Vec<Event>, Box<dyn Error + Send + Sync>> {
+    let path = data_dir().join(IMPORTED_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read '{}': {}.", path.display(), e).into()),
+    };
+    let events: Vec<CachedEvent> = serde_json::from_str(&contents).map_err(|e| format!("Failed to read '{}': {}.", path.display(), e))?;
+    Ok(events.into_iter().map(Event::from).collect())
+}
+
+/// Adds the imported events to the fetched ones, leaving out any the timetable already has.
+fn merge_imported(events: &mut Vec<Event>) {
+    match imported_events() {
+        Ok(imported) => {
+            let known: std::collections::HashSet<String> = events.iter().map(event_id).collect();
+            events.extend(imported.into_iter().filter(|event| !known.contains(&event_id(event))));
+        }
+        Err(e) => log_warning(format!("Leaving out the imported events: {}", e)),
+    }
+}
+
+fn import_command(ical: Option<PathBuf>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let path = data_dir().join(IMPORTED_FILE);
+    let Some(ical) = ical else {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        };
+    };
+    let file = fs::File::open(&ical).map_err(|e| format!("Failed to open '{}': {}.", ical.display(), e))?;
+    let mut events = Vec::new();
+    let mut skipped = 0;
+    for calendar in ical::IcalParser::new(std::io::BufReader::new(file)) {
+        let calendar = calendar.map_err(|e| format!("'{}' is not a valid iCalendar file: {}.", ical.display(), e))?;
+        for vevent in &calendar.events {
+            match event_from_vevent(vevent) {
+                Some(event) => events.push(event),
+                None => skipped += 1,
+            }
+        }
+    }
+    if skipped > 0 {
+        log_warning(format!("{} events skipped for lacking a title or a readable start time.", skipped));
+    }
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    write_atomically(&path, &serde_json::to_vec_pretty(&events.iter().map(CachedEvent::from).collect::<Vec<_>>())?)?;
+    eprintln!("Imported {} events from '{}'.", events.len(), ical.display());
+    Ok(())
+}
+
+// --- Academic Calendar ---
+
+/// Where a date falls in the academic calendar.
+#[derive(Debug, Clone, Copy)]
+enum AcademicWeek {
+    Teaching { semester: u8, week: i64 },
+    Reading { semester: u8 },
+}
+
+impl AcademicWeek {
+    fn semester(self) -> u8 {
+        match self {
+            AcademicWeek::Teaching { semester, .. } | AcademicWeek::Reading { semester } => semester,
+        }
+    }
+}
+
+impl std::fmt::Display for AcademicWeek {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            AcademicWeek::Teaching { semester, week } => write!(f, "S{} W{}", semester, week),
+            AcademicWeek::Reading { semester } => write!(f, "S{} reading week", semester),
+        }
+    }
+}
+
+impl AcademicCalendar {
+    /// The semesters with both dates set, as (number, first day, last day).
+    fn semesters(&self) -> Vec<(u8, NaiveDate, NaiveDate)> {
+        [(1, self.semester_1_start, self.semester_1_end), (2, self.semester_2_start, self.semester_2_end)]
+            .into_iter()
+            .filter_map(|(number, start, end)| Some((number, start?, end?)))
+            .collect()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (number, start, end) in [(1, self.semester_1_start, self.semester_1_end), (2, self.semester_2_start, self.semester_2_end)] {
+            match (start, end) {
+                (Some(start), Some(end)) if end < start => {
+                    return Err(format!("[academic_calendar] semester_{}_end is before semester_{}_start", number, number));
+                }
+                (Some(_), None) | (None, Some(_)) => {
+                    return Err(format!("[academic_calendar] needs both semester_{}_start and semester_{}_end", number, number));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The semester and teaching week of `date`. Weeks count from the one the semester starts in,
+    /// skipping reading weeks; dates outside both semesters have none.
+    fn week_of(&self, date: NaiveDate) -> Option<AcademicWeek> {
+        let (semester, start, _) = self.semesters().into_iter().find(|(_, start, end)| (*start..=*end).contains(&date))?;
+        let monday = week_start(date);
+        let reading_weeks: Vec<NaiveDate> = self.reading_weeks.iter().map(|reading| week_start(*reading)).collect();
+        if reading_weeks.contains(&monday) {
+            return Some(AcademicWeek::Reading { semester });
+        }
+        let first = week_start(start);
+        let skipped = reading_weeks.iter().filter(|reading| **reading >= first && **reading < monday).count() as i64;
+        Some(AcademicWeek::Teaching { semester, week: (monday - first).num_days() / 7 + 1 - skipped })
+    }
+}
+
+// --- Academic Year Archive ---
+
+/// The first day of the academic year written like "2024-25".
+fn academic_year_start(year: &str) -> Result<NaiveDate, Box<dyn Error + Send + Sync>> {
+    let invalid = || format!("Invalid academic year '{}'; write it like 2024-25.", year);
+    let (first, second) = year.split_once('-').ok_or_else(invalid)?;
+    let first: i32 = first.parse().map_err(|_| invalid())?;
+    let second: i32 = second.parse().map_err(|_| invalid())?;
+    if (first + 1) % 100 != second {
+        return Err(invalid().into());
+    }
+    NaiveDate::from_ymd_opt(first, 9, 1).ok_or_else(|| invalid().into())
+}
+
+/// The days to archive for the academic year starting `year_start`, as a first day and the day
+/// after the last: from the start of the first semester to the end of the second, as far as the
+/// calendar gives term dates within the year, or else September to August.
+fn archive_span(year_start: NaiveDate, calendar: &AcademicCalendar) -> (NaiveDate, NaiveDate) {
+    let year_end = year_start.with_year(year_start.year() + 1).unwrap();
+    let term_dates: Vec<NaiveDate> = [calendar.semester_1_start, calendar.semester_1_end, calendar.semester_2_start, calendar.semester_2_end]
+        .into_iter()
+        .flatten()
+        .filter(|date| (year_start..year_end).contains(date))
+        .collect();
+    match (term_dates.iter().min(), term_dates.iter().max()) {
+        (Some(&first), Some(&last)) => (first, last + Duration::days(1)),
+        _ => (year_start, year_end),
+    }
+}
+
+/// Bristol's teaching blocks: the semester from `[academic_calendar]` where it covers the date,
+/// otherwise TB1 from September to January and TB2 for the rest of the year.
+fn teaching_block(date: NaiveDate, calendar: &AcademicCalendar) -> &'static str {
+    match calendar.week_of(date).map(AcademicWeek::semester) {
+        Some(1) => "TB1",
+        Some(_) => "TB2",
+        None if date.month() >= 9 || date.month() == 1 => "TB1",
+        None => "TB2",
+    }
+}
+
+/// Reads back an event written by `event_json`.
+fn event_from_json(value: &serde_json::Value) -> Option<Event> {
+    let text = |key: &str| value[key].as_str().map(str::to_string);
+    Some(Event {
+        title: text("title")?,
+        event_type: text("type")?,
+        start: text("start")?,
+        end: text("end")?,
+        location: text("location")?,
+        teacher_name: text("lecturer"),
+        booking_id: text("link").and_then(|link| link.rsplit('/').next().map(str::to_string)),
+        level: text("level"),
+        issue: None,
+    }.composed())
+}
+
+fn archive_summary(year: &str, events: &[Event], calendar: &AcademicCalendar) -> String {
+    let mut blocks: BTreeMap<&str, BTreeMap<&str, i64>> = BTreeMap::new();
+    for event in events {
+        let Some(start) = parse_time(&event.start) else { continue };
+        *blocks.entry(teaching_block(start.date_naive(), calendar)).or_default().entry(&event.title).or_default() += event_minutes(event);
+    }
+    let total: i64 = events.iter().map(event_minutes).sum();
+    let mut out = format!("Academic year {}: {} sessions, {}\n", year, events.len(), format_minutes(total));
+    for (block, units) in blocks {
+        writeln!(out, "\n{}: {}", block, format_minutes(units.values().sum())).unwrap();
+        let width = units.keys().map(|unit| unit.chars().count()).max().unwrap_or(0);
+        for (unit, minutes) in units {
+            writeln!(out, "  {:<width$}  {}", unit, format_minutes(minutes), width = width).unwrap();
+        }
+    }
+    out
+}
+
+/// Writes a JSON file per week of the academic year, then a CSV and summary of the whole year.
+/// Weeks already written are read back rather than fetched again, so an interrupted archive
+/// can be resumed.
+fn archive(config: Config, year: &str, out: &Path, force: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (first_day, last_day) = archive_span(academic_year_start(year)?, &config.academic_calendar);
+    fs::create_dir_all(out).map_err(|e| format!("Failed to create '{}': {}.", out.display(), e))?;
+
+    let mut mondays = Vec::new();
+    let mut monday = first_day - Duration::days(first_day.weekday().num_days_from_monday() as i64);
+    while monday < last_day {
+        mondays.push(monday);
+        monday += Duration::days(7);
+    }
+
+    let progress = MultiProgress::new();
+    let overall = progress.add(ProgressBar::new(mondays.len() as u64));
+    overall.set_style(ProgressStyle::default_bar().template("{bar:40} {pos}/{len} weeks")?);
+    let mut events: Vec<Event> = Vec::new();
+    let mut failed = Vec::new();
+    for monday in mondays {
+        let path = out.join(format!("week-{}.json", monday));
+        let week = progress.insert_before(&overall, ProgressBar::new_spinner());
+        week.set_message(format!("Week of {}", monday));
+        let existing = fs::read_to_string(&path).ok().filter(|contents| !contents.trim().is_empty() && !force);
+        // A week file that doesn't parse, say from a run cut short, is fetched again.
+        let existing = existing.and_then(|contents| match serde_json::from_str::<Vec<serde_json::Value>>(&contents) {
+            Ok(values) => Some(values),
+            Err(e) => {
+                progress.suspend(|| log_warning(format!("'{}' is not a week written by bstt ({}); fetching it again.", path.display(), e)));
+                None
+            }
+        });
+        let week_events = match existing {
+            Some(values) => {
+                week.finish_with_message(format!("Week of {}: already written", monday));
+                values.iter().filter_map(event_from_json).collect()
+            }
+            None => {
+                let start = monday.and_time(NaiveTime::MIN).and_utc();
+                match fetch_range(&config, start, start + Duration::days(7)) {
+                    Ok((fetched, report)) if !report.is_partial() => {
+                        write_atomically(&path, render_structured(ExportFormat::Json, &fetched.events, &config.display, None)?.as_bytes())
+                            .map_err(|e| format!("Failed to write '{}': {}.", path.display(), e))?;
+                        week.finish_with_message(format!("Week of {}: {} {} events", monday, outcome_mark(true), fetched.events.len()));
+                        // In the same form as weeks read back, so that duplicates can be recognised.
+                        fetched.events.iter().filter_map(|event| event_from_json(&event_json(event))).collect()
+                    }
+                    Ok(_) | Err(_) => {
+                        week.finish_with_message(format!("Week of {}: {} failed", monday, outcome_mark(false)));
+                        failed.push(monday);
+                        Vec::new()
+                    }
+                }
+            }
+        };
+        events.extend(week_events);
+        overall.inc(1);
+    }
+    overall.finish();
+
+    if !failed.is_empty() {
+        return Err(format!("{} weeks couldn't be fetched; run the same command again to retry just those.", failed.len()).into());
+    }
+    // An event on a week boundary is returned for both weeks.
+    let mut seen = std::collections::HashSet::new();
+    events.retain(|event| seen.insert(event_id(event)));
+    let write = |name: &str, contents: String| {
+        let path = out.join(name);
+        write_atomically(&path, contents.as_bytes()).map_err(|e| format!("Failed to write '{}': {}.", path.display(), e))
+    };
+    write("timetable.csv", render_structured(ExportFormat::Csv, &events, &config.display, None)?)?;
+    write("summary.txt", archive_summary(year, &events, &config.academic_calendar))?;
+    eprintln!("Archived {} events to '{}'.", events.len(), out.display());
+    Ok(())
+}
+
+// --- Building Maps ---
+
+#[derive(Deserialize)]
+struct BuildingMap {
+    buildings: HashMap<String, String>,
+}
+
+/// A map link for the building an event is in, from the bundled `data/buildings.toml`.
+fn map_url(location: &str) -> Option<&'static str> {
+    static BUILDING_MAP: OnceLock<BuildingMap> = OnceLock::new();
+    let map = BUILDING_MAP.get_or_init(|| toml::from_str(BUILDINGS).expect("bundled buildings.toml is valid"));
+    let building = location.split(':').next()?.trim();
+    map.buildings.get(building).map(String::as_str)
+}
+
+// --- Online Events ---
+
+/// `[display] online_locations` and `online_titles`, lower-cased.
+static ONLINE_PATTERNS: OnceLock<(Vec<String>, Vec<String>)> = OnceLock::new();
+
+/// Whether the event happens online rather than in a room, going by its location and title.
+fn is_online(event: &Event) -> bool {
+    let (locations, titles) = ONLINE_PATTERNS.get_or_init(|| (default_online_locations(), default_online_titles()));
+    let (location, title) = (event.location.to_lowercase(), event.title.to_lowercase());
+    locations.iter().any(|pattern| location.contains(pattern.as_str())) || titles.iter().any(|pattern| title.contains(pattern.as_str()))
+}
+
+/// The first web address in the event's location or title, which for an online event is
+/// usually where to join it.
+fn joining_url(event: &Event) -> Option<&str> {
+    [&event.location, &event.title].into_iter()
+        .flat_map(|text| text.split_whitespace())
+        .map(|word| word.trim_start_matches(['(', '[', '<']))
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(|word| word.trim_end_matches([')', ']', '>', ',', '.', ';']))
+}
+
+/// The event's location for narrow views: "online", or the compressed room.
+fn short_location(event: &Event) -> String {
+    if is_online(event) { "online".to_string() } else { compress_location(&event.location) }
+}
+
+/// The building map for the event; online events have none, whatever their location says.
+fn event_map_url(event: &Event) -> Option<&'static str> {
+    if is_online(event) { None } else { map_url(&event.location) }
+}
+
+fn online_label() -> &'static str {
+    if ascii_only() { "Online" } else { "💻 Online" }
+}
+
+// --- Bank Holidays ---
+
+#[derive(Deserialize, Debug)]
+struct BankHoliday {
+    title: String,
+    date: NaiveDate,
+}
+
+/// A division of gov.uk's bank-holidays.json.
+#[derive(Deserialize)]
+struct HolidayDivision {
+    events: Vec<BankHoliday>,
+}
+
+fn parse_bank_holidays(json: &str) -> Result<Vec<BankHoliday>, Box<dyn Error + Send + Sync>> {
+    let mut divisions: HashMap<String, HolidayDivision> = serde_json::from_str(json)?;
+    let division = divisions.remove(BANK_HOLIDAY_DIVISION).ok_or_else(|| format!("There are no holidays for {}.", BANK_HOLIDAY_DIVISION))?;
+    Ok(division.events)
+}
+
+/// The holidays saved by `bstt update-holidays`, or the bundled `data/bank_holidays.json` until
+/// it has been run.
+fn bank_holidays() -> &'static [BankHoliday] {
+    static HOLIDAYS: OnceLock<Vec<BankHoliday>> = OnceLock::new();
+    HOLIDAYS.get_or_init(|| {
+        let path = data_dir().join(BANK_HOLIDAYS_FILE);
+        if let Ok(json) = fs::read_to_string(&path) {
+            match parse_bank_holidays(&json) {
+                Ok(holidays) => return holidays,
+                Err(e) => log_warning(format!("Using the bundled bank holidays, as '{}' can't be read: {}", path.display(), e)),
+            }
+        }
+        parse_bank_holidays(BANK_HOLIDAYS).expect("bundled bank_holidays.json is valid")
+    })
+}
+
+fn bank_holiday(date: NaiveDate) -> Option<&'static BankHoliday> {
+    bank_holidays().iter().find(|holiday| holiday.date == date)
+}
+
+fn list_bank_holidays(config: &Config) {
+    let today = display_today();
+    let until = today + Duration::days(config.api.fetch_days_future);
+    let upcoming: Vec<&BankHoliday> = bank_holidays().iter().filter(|holiday| today <= holiday.date && holiday.date <= until).collect();
+    if upcoming.is_empty() {
+        println!("No bank holidays before {}.", until);
+    }
+    if bank_holidays().iter().all(|holiday| holiday.date < until) {
+        log_warning("The bank holiday list ends before then; run `bstt update-holidays` for later years.");
+    }
+    for holiday in upcoming {
+        println!("{}  {}", paint(&holiday.date.format("%a %d %b %Y").to_string(), Role::Time), holiday.title);
+    }
+}
+
+fn update_holidays() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let body = reqwest::blocking::Client::new().get(BANK_HOLIDAYS_URL).header(header::USER_AGENT, USER_AGENT).send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| format!("Could not download the bank holidays: {}", e))?;
+    let holidays = parse_bank_holidays(&body).map_err(|e| format!("gov.uk sent an unexpected bank holiday list: {}", e))?;
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    write_atomically(&dir.join(BANK_HOLIDAYS_FILE), body.as_bytes())?;
+    let last = holidays.iter().map(|holiday| holiday.date).max().map(|date| date.to_string()).unwrap_or_default();
+    eprintln!("Saved {} bank holidays, up to {}.", holidays.len(), last);
+    Ok(())
+}
+
+// --- Full Timetable Display (FIXED) ---
+
+/// How the full timetable is filtered and decorated, gathered from flags and config.
+#[derive(Debug, Default)]
+struct ViewOptions {
+    numbered: bool,
+    show_links: bool,
+    /// Whether to emit OSC 8 hyperlinks.
+    hyperlinks: bool,
+    display: DisplayConfig,
+    calendar: AcademicCalendar,
+    search: Option<String>,
+    types: Vec<String>,
+    exclude: Vec<String>,
+    min_duration: i64,
+    /// Set when the day view is leaving out today's finished events.
+    hide_finished: bool,
+    weekday_labels: Option<WeekdayLabel>,
+    merge_adjacent: bool,
+    show_level: bool,
+    reverse: bool,
+    /// From `--width`.
+    width: Option<u16>,
+}
+
+/// The day a view is of, and when it's being shown. Anything that depends on the current time
+/// decides through `is_today`, so that it only decorates views of today.
+struct ViewContext {
+    target_date: NaiveDate,
+    now: DateTime<FixedOffset>,
+    is_today: bool,
+}
+
+impl ViewContext {
+    fn new(target_date: NaiveDate) -> Self {
+        let now = status_now();
+        ViewContext { target_date, now, is_today: target_date == now.date_naive() }
+    }
+
+    /// Names the target date relative to today, e.g. " (next Monday)".
+    fn day_label(&self, display: &DisplayConfig) -> String {
+        format!(" ({})", relative_day_label(self.target_date, self.now.date_naive(), display.week_start))
+    }
+
+    /// Whether the event is on now, which only a view of today can show.
+    fn is_current(&self, event: &Event) -> bool {
+        self.is_today && is_in_progress(event, self.now)
+    }
+}
+
+/// How `target` relates to `today`. The days either side are "Yesterday" and "Tomorrow"; other days
+/// in today's week are "this Friday", those in the weeks either side "next Monday" or "last Tuesday",
+/// and anything further "in 12 days" or "12 days ago". Weeks begin on `week_start`.
+fn relative_day_label(target: NaiveDate, today: NaiveDate, week_start: Weekday) -> String {
+    let days = target.signed_duration_since(today).num_days();
+    let start_of_week = |date: NaiveDate| date - Duration::days(date.weekday().days_since(week_start) as i64);
+    let weeks = start_of_week(target).signed_duration_since(start_of_week(today)).num_days() / 7;
+    let weekday = target.format("%A");
+    match (days, weeks) {
+        (0, _) => "Today".to_string(),
+        (1, _) => "Tomorrow".to_string(),
+        (-1, _) => "Yesterday".to_string(),
+        (_, 0) => format!("this {}", weekday),
+        (_, 1) => format!("next {}", weekday),
+        (_, -1) => format!("last {}", weekday),
+        (days, _) if days > 0 => format!("in {} days", days),
+        (days, _) => format!("{} days ago", -days),
+    }
+}
+
+fn weekday_label(date: NaiveDate, style: WeekdayLabel) -> String {
+    match style {
+        WeekdayLabel::Full => date.format("%A").to_string(),
+        WeekdayLabel::Short => date.format("%a").to_string(),
+        WeekdayLabel::Letter => date.format("%a").to_string()[..1].to_string(),
+    }
+}
+
+/// Case-insensitive match against every text field of the event.
+fn matches_search(event: &Event, term: &str) -> bool {
+    let term = term.to_lowercase();
+    [Some(&event.title), Some(&event.location), Some(&event.event_type), event.teacher_name.as_ref()]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(&term))
+}
+
+fn is_pinned(event: &Event, options: &ViewOptions) -> bool {
+    let title = event.title.to_lowercase();
+    options.display.pinned.iter().any(|term| title.contains(&term.to_lowercase()))
+}
+
+/// Moves pinned events to the front, keeping both groups in their current order.
+fn pin_events(daily_events: &mut [Event], options: &ViewOptions) {
+    daily_events.sort_by_key(|event| !is_pinned(event, options));
+}
+
+fn is_excluded(event: &Event, terms: &[String]) -> bool {
+    terms.iter().map(|term| term.to_lowercase()).any(|term| {
+        [&event.title, &event.event_type, &event.location].iter().any(|field| field.to_lowercase().contains(&term))
+    })
+}
+
+/// How many events a view's filters were given, and how many each took out.
+#[derive(Debug, Default, Clone, Copy)]
+struct FilterCounts {
+    considered: usize,
+    by_type: usize,
+    by_search: usize,
+    excluded: usize,
+    too_short: usize,
+    merged: usize,
+}
+
+impl std::ops::AddAssign for FilterCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.considered += other.considered;
+        self.by_type += other.by_type;
+        self.by_search += other.by_search;
+        self.excluded += other.excluded;
+        self.too_short += other.too_short;
+        self.merged += other.merged;
+    }
+}
+
+/// Exclusions are applied after the other filters, so they can carve exceptions out of a
+/// `--type` or `--search`. Reversing comes after merging, which needs events in time order.
+fn apply_filters(daily_events: &mut Vec<Event>, options: &ViewOptions) -> FilterCounts {
+    let mut counts = FilterCounts { considered: daily_events.len(), ..Default::default() };
+    let removed = |daily_events: &mut Vec<Event>, keep: &dyn Fn(&Event) -> bool| {
+        let before = daily_events.len();
+        daily_events.retain(|event| keep(event));
+        before - daily_events.len()
+    };
+    if !options.types.is_empty() {
+        counts.by_type = removed(daily_events, &|event| options.types.iter().any(|event_type| event_type.eq_ignore_ascii_case(&event.event_type)));
+    }
+    if let Some(term) = &options.search {
+        counts.by_search = removed(daily_events, &|event| matches_search(event, term));
+    }
+    if !options.exclude.is_empty() {
+        counts.excluded = removed(daily_events, &|event| !is_excluded(event, &options.exclude));
+    }
+    if options.min_duration > 0 {
+        counts.too_short = removed(daily_events, &|event| event_minutes(event) >= options.min_duration);
+        if counts.too_short > 0 {
+            log_verbose(format!("Hid {} events shorter than {} minutes", counts.too_short, options.min_duration));
+        }
+    }
+    if options.merge_adjacent {
+        let before = daily_events.len();
+        merge_adjacent(daily_events);
+        counts.merged = before - daily_events.len();
+    }
+    if options.reverse {
+        daily_events.reverse();
+    }
+    counts
+}
+
+/// Collapses back-to-back events with the same title, type and location (a lab booked as
+/// three one-hour sessions, say) into one. Flagged events are left alone.
+fn merge_adjacent(daily_events: &mut Vec<Event>) {
+    let same_instant = |a: &str, b: &str| parse_timestamp(a) == parse_timestamp(b);
+    let mut merged: Vec<Event> = Vec::with_capacity(daily_events.len());
+    for event in daily_events.drain(..) {
+        let block = merged.iter_mut().rev().find(|block| {
+            block.issue.is_none() && event.issue.is_none()
+                && block.title == event.title && block.event_type == event.event_type && block.location == event.location
+                && same_instant(&block.end, &event.start)
+        });
+        match block {
+            Some(block) => block.end = event.end,
+            None => merged.push(event),
+        }
+    }
+    *daily_events = merged;
+}
+
+/// Reminds the reader which filters are hiding events from the view.
+fn filter_header(options: &ViewOptions) -> String {
+    let mut out = String::new();
+    if !options.types.is_empty() {
+        writeln!(out, " {} {}", "Types:".bold(), options.types.join(", ")).unwrap();
+    }
+    if let Some(term) = &options.search {
+        writeln!(out, " {} {}", "Search:".bold(), term).unwrap();
+    }
+    if !options.exclude.is_empty() {
+        writeln!(out, " {} {}", "Excluding:".bold(), paint(&options.exclude.join(", "), Role::Warning)).unwrap();
+    }
+    if options.min_duration > 0 {
+        writeln!(out, " {} {} minutes", "At least:".bold(), options.min_duration).unwrap();
+    }
+    if options.hide_finished {
+        writeln!(out, " {} the rest of today", "Showing:".bold()).unwrap();
+    }
+    out
+}
+
+/// Whether any of the event's [start, end) falls on `date`, so that a session running past
+/// midnight shows on both days.
+fn event_spans_date(event: &Event, date: NaiveDate) -> bool {
+    let (Some(start), Some(end)) = (parse_time(&event.start), parse_time(&event.end)) else { return false };
+    let last_instant = if end > start { end - Duration::nanoseconds(1) } else { start };
+    start.date_naive() <= date && date <= last_instant.date_naive()
+}
+
+/// The events on `target_date`, in display order. Ties are broken by title so that row
+/// numbers stay the same between invocations.
+fn day_events(events: &[Event], target_date: NaiveDate) -> Vec<Event> {
+    let mut daily_events: Vec<Event> = events.iter()
+        .filter(|event| event_spans_date(event, target_date))
+        .cloned()
+        .collect();
+
+    daily_events.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.title.cmp(&b.title)));
+    daily_events
+}
+
+/// The buildings of the day's events in the order they're visited, with the time each is entered,
+/// e.g. "09:00 Phys → 11:00 Queens". Consecutive events in the same building are one visit.
+fn render_rooms(daily_events: &[Event], display: &DisplayConfig) -> String {
+    let mut visits: Vec<(DateTime<FixedOffset>, String)> = daily_events.iter()
+        .filter(|event| !event.location.trim().is_empty() && !is_online(event))
+        .filter_map(|event| Some((parse_time(&event.start)?, event)))
+        .map(|(start, event)| {
+            let location = compress_location(&event.location);
+            (start, location.split(':').next().unwrap_or_default().trim().to_string())
+        })
+        .collect();
+    visits.sort_by_key(|(start, _)| *start);
+    visits.dedup_by(|later, earlier| later.1 == earlier.1);
+    if visits.is_empty() {
+        return "No rooms to visit.\n".to_string();
+    }
+    let route: Vec<String> = visits.iter().map(|(start, building)| format!("{} {}", paint(&format_time(*start, display), Role::Time), building)).collect();
+    format!("{}\n", route.join(" → "))
+}
+
+/// The longest a `bstt gantt` row label gets before it's cut short.
+const GANTT_LABEL_WIDTH: usize = 24;
+
+struct GanttRow {
+    label: String,
+    /// The columns each event covers, end exclusive.
+    spans: Vec<(usize, usize)>,
+    /// When the row's last event ends, in minutes since midnight.
+    free_from: i64,
+}
+
+/// The day as a timeline from `hours.0` to `hours.1` o'clock, `width` columns wide: a row for
+/// each unit (more where its events overlap), with `█` while an event is on and `.` otherwise.
+fn render_gantt(daily_events: &[Event], target_date: NaiveDate, hours: (u32, u32), width: usize, display: &DisplayConfig) -> String {
+    let midnight = target_date.and_hms_opt(0, 0, 0).expect("midnight exists");
+    let (axis_start, axis_end) = (hours.0 as i64 * 60, hours.1 as i64 * 60);
+    let column = |minutes: i64| ((minutes.clamp(axis_start, axis_end) - axis_start) as f64 / (axis_end - axis_start) as f64 * width as f64).round() as usize;
+    let mut rows: Vec<GanttRow> = Vec::new();
+    let mut outside = 0;
+    for event in daily_events {
+        let (Some(start), Some(end)) = (parse_time(&event.start), parse_time(&event.end)) else { continue };
+        let (start, end) = ((start.naive_local() - midnight).num_minutes(), (end.naive_local() - midnight).num_minutes());
+        if end <= axis_start || start >= axis_end {
+            outside += 1;
+            continue;
+        }
+        // Even a short event gets a column, so that it doesn't vanish.
+        let from = column(start).min(width - 1);
+        let span = (from, column(end).max(from + 1));
+        let label = compress_title(&event.title, display.density);
+        match rows.iter_mut().find(|row| row.label == label && row.free_from <= start) {
+            Some(row) => {
+                row.spans.push(span);
+                row.free_from = end;
+            }
+            None => rows.push(GanttRow { label, spans: vec![span], free_from: end }),
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, " {} {}", "Timeline for".bold(), target_date.format("%A, %d %B %Y").to_string().bold()).unwrap();
+    if rows.is_empty() {
+        out.push_str(if outside == 0 { " No events on this day.\n" } else { " No events in these hours.\n" });
+    } else {
+        let label_width = rows.iter().map(|row| row.label.chars().count()).max().unwrap_or(0).min(GANTT_LABEL_WIDTH);
+        // Hour marks go wherever there's room for them after the last.
+        let mut axis = vec![' '; width + 2];
+        let mut free_from = 0;
+        for hour in hours.0..=hours.1 {
+            let at = column(hour as i64 * 60);
+            if at >= free_from {
+                for (offset, digit) in format!("{:02}", hour).chars().enumerate() {
+                    axis[at + offset] = digit;
+                }
+                free_from = at + 3;
+            }
+        }
+        writeln!(out, " {:label_width$} {}", "", axis.iter().collect::<String>().trim_end()).unwrap();
+        for row in &rows {
+            let mut bar = vec!['.'; width];
+            for &(from, to) in &row.spans {
+                bar[from..to].fill('█');
+            }
+            let label = if row.label.chars().count() > label_width {
+                format!("{}…", row.label.chars().take(label_width - 1).collect::<String>())
+            } else {
+                row.label.clone()
+            };
+            writeln!(out, " {:label_width$} {}", label, bar.iter().collect::<String>()).unwrap();
+        }
+    }
+    if outside > 0 {
+        writeln!(out, " {}", format!("Not shown: {} outside {:02}:00–{:02}:00", if outside == 1 { "1 event".to_string() } else { format!("{} events", outside) }, hours.0, hours.1).dimmed()).unwrap();
+    }
+    out
+}
+
+/// A gentle warning when the day's events add up to more than `[display] busy_hours`. Events
+/// with implausible times are left out of the sum, as they'd make any day look heavy.
+fn busy_banner(daily_events: &[Event], display: &DisplayConfig) -> String {
+    let minutes: i64 = daily_events.iter().filter(|event| event.issue.is_none()).map(event_minutes).sum();
+    if display.busy_hours <= 0.0 || minutes as f64 <= display.busy_hours * 60.0 {
+        return String::new();
+    }
+    format!(" {}\n", paint(&format!("⚠ Heavy day: {} of contact time", format_minutes(minutes)), Role::Warning))
+}
+
+fn render_timetable(daily_events: Vec<Event>, context: &ViewContext, options: &ViewOptions, report: &FetchReport) -> String {
+    let target_date = context.target_date;
+    let mut out = String::new();
+    let weekday = weekday_label(target_date, options.weekday_labels.unwrap_or(WeekdayLabel::Full));
+    let date_str = format!("{}, {}", weekday, target_date.format("%d %B %Y"));
+    let zone_label = zone_label(target_date).map(|zone| format!(" ({})", zone)).unwrap_or_default();
+
+    writeln!(out, " {} {}{}{}", "Timetable for".bold(), date_str.bold(), context.day_label(&options.display).bold(), zone_label.bold()).unwrap();
+    if let Some(holiday) = bank_holiday(target_date) {
+        writeln!(out, " 🏦 {} {}", "Bank Holiday:".bold(), paint(&holiday.title, Role::Warning)).unwrap();
+    }
+    out.push_str(&filter_header(options));
+    out.push_str(&busy_banner(&daily_events, &options.display));
+
+    if daily_events.is_empty() && !report.covers(target_date) {
+        let setting = if target_date < context.now.date_naive() { "fetch_days_past" } else { "fetch_days_future" };
+        let message = format!(
+            "The requested date ({}) is outside the fetched range ({} to {}); pass --window or adjust {}.",
+            target_date,
+            to_display(&report.window_start).date_naive(),
+            to_display(&report.window_end).date_naive(),
+            setting,
+        );
+        writeln!(out, "\n{}", paint(&message, Role::Warning)).unwrap();
+        out.push_str(&report_footer(report));
+        return out;
+    }
+    if daily_events.is_empty() {
+        writeln!(out, "\n{}", paint("No events scheduled for this day.", Role::Good)).unwrap();
+        out.push_str(&report_footer(report));
+        return out;
+    }
+
+    let mut table = Table::new();
+    load_table_preset(&mut table).set_content_arrangement(ContentArrangement::Dynamic).set_width(table_width(options.width));
+    
+    let mut header = vec![
+        Cell::new("Time").fg(cell_colour(Role::Heading)), Cell::new("Type").fg(cell_colour(Role::Heading)),
+        Cell::new("Event").fg(cell_colour(Role::Heading)), Cell::new("Location").fg(cell_colour(Role::Heading)),
+        Cell::new("Lecturer").fg(cell_colour(Role::Heading)),
+    ];
+    if options.show_level { header.push(Cell::new("Level").fg(cell_colour(Role::Heading))); }
+    if options.numbered { header.insert(0, Cell::new("#").fg(cell_colour(Role::Heading))); }
+    table.set_header(header);
+
+    for (index, event) in daily_events.into_iter().enumerate() {
+        let start_time = parse_time(&event.start).unwrap();
+        let end_time = parse_time(&event.end).unwrap();
+        let mut time_str = format!("{} - {}", format_time(start_time, &options.display), format_time(end_time, &options.display));
+        if start_time.date_naive() < target_date {
+            time_str = format!("{}\n↙ continued from {}", time_str, start_time.format("%a %d %b"));
+        }
+        if let Some(issue) = event.issue {
+            time_str = format!("{}\n{}", time_str, issue.describe());
+        }
+        let current = context.is_current(&event);
+        if current {
+            time_str = format!("▶ {}", time_str);
+        }
+        // Flagged times are bold as well as coloured, besides carrying the text of the warning.
+        let time_cell = match event.issue {
+            Some(_) => Cell::new(time_str).fg(cell_colour(Role::Problem)).add_attribute(Attribute::Bold),
+            None if current => Cell::new(time_str).fg(cell_colour(Role::Time)).add_attribute(Attribute::Bold),
+            None => Cell::new(time_str).fg(cell_colour(Role::Time)),
+        };
+        
+        // Lecturers are already tidied into "A, B", so the first is everything before the comma.
+        let main_lecturer = event.teacher_name.as_deref().and_then(|names| names.split(',').next()).unwrap_or("");
+
+        let mut row = vec![
+            time_cell, Cell::new(&event.event_type).fg(cell_colour(Role::EventType)),
+            title_cell(&event, options), location_cell(&event, options),
+            Cell::new(main_lecturer).fg(cell_colour(Role::Lecturer)),
+        ];
+        if options.show_level { row.push(Cell::new(event.level.as_deref().unwrap_or("-"))); }
+        if options.numbered { row.insert(0, Cell::new(index + 1)); }
+        table.add_row(row);
+    }
+    if let Some(width) = options.display.max_title_width {
+        // Allow for the cell's padding, so the title itself gets the full width.
+        let event_column = if options.numbered { 3 } else { 2 };
+        if let Some(column) = table.column_mut(event_column) {
+            column.set_constraint(ColumnConstraint::UpperBoundary(Width::Fixed(width.saturating_add(2))));
+        }
+    }
+    writeln!(out, "{}", table).unwrap();
+    out.push_str(&report_footer(report));
+    out
+}
+
+/// Guesses from the environment whether the terminal understands OSC 8 hyperlinks. Terminals
+/// that don't usually print the link text alone, but some older ones print the escapes raw.
+fn terminal_supports_hyperlinks() -> bool {
+    let var = |name| env::var(name).unwrap_or_default();
+    ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&var("TERM_PROGRAM").as_str())
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|version| version >= 5000)
+        || ["KITTY_WINDOW_ID", "WT_SESSION", "DOMTERM"].iter().any(|name| env::var_os(name).is_some())
+        || ["xterm-kitty", "foot", "alacritty", "xterm-ghostty", "wezterm"].iter().any(|term| var("TERM").starts_with(term))
+}
+
+/// Wraps `text` in an OSC 8 hyperlink. The escapes take no columns, so tables still line up.
+fn hyperlink(text: &str, url: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\", url, text)
+}
+
+fn unit_link<'a>(event: &Event, options: &'a ViewOptions) -> Option<&'a str> {
+    let title = event.title.to_lowercase();
+    options.display.unit_links.iter().find(|(unit, _)| title.contains(&unit.to_lowercase())).map(|(_, url)| url.as_str())
+}
+
+/// Cuts `text` to at most `width` characters, ending it with an ellipsis if anything was cut.
+fn truncate_text(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let ellipsis = if ascii_only() { "..." } else { "…" };
+    let kept: String = text.chars().take(width.saturating_sub(ellipsis.chars().count())).collect();
+    format!("{}{}", kept.trim_end(), ellipsis)
+}
+
+fn title_cell(event: &Event, options: &ViewOptions) -> Cell {
+    let title = match options.display.max_title_width.filter(|_| options.display.truncate_titles) {
+        // The pin and its space take three columns.
+        Some(width) => truncate_text(&event.title, usize::from(width).saturating_sub(if is_pinned(event, options) { 3 } else { 0 })),
+        None => event.title.clone(),
+    };
+    let mut title = match unit_link(event, options).filter(|_| options.hyperlinks) {
+        Some(url) => hyperlink(&title, url),
+        None => title,
+    };
+    if is_pinned(event, options) { title = format!("📌 {}", title); }
+    let cell = match deep_link(event).filter(|_| options.show_links) {
+        Some(url) => Cell::new(format!("{}\n{}", title, url.dimmed().underline())),
+        None => Cell::new(title),
+    };
+    // A unit's own colour beats the focus colour, though focused events are still bold.
+    let cell = match module_colour(event, &options.display) {
+        Some(colour) => cell.fg(colour),
+        None if is_focused(event) => cell.fg(cell_colour(Role::Heading)),
+        None => cell,
+    };
+    if is_focused(event) { cell.add_attribute(Attribute::Bold) } else { cell }
+}
+
+fn location_cell(event: &Event, options: &ViewOptions) -> Cell {
+    if is_online(event) {
+        return match joining_url(event) {
+            Some(url) if options.hyperlinks => Cell::new(hyperlink(online_label(), url)),
+            Some(url) => Cell::new(format!("{}\n{}", paint(online_label(), Role::Location), url.dimmed().underline())),
+            None => Cell::new(online_label()),
+        }.fg(cell_colour(Role::Location));
+    }
+    let location = event.location.as_str();
+    let url = map_url(location);
+    let text = match url.filter(|_| options.hyperlinks) {
+        Some(url) => hyperlink(location, url),
+        None => location.to_string(),
+    };
+    let colour = match options.display.color_by {
+        Some(ColorBy::Location) => LOCATION_PALETTE[location_colour_index(location, options.display.location_palette_size)],
+        None => cell_colour(Role::Location),
+    };
+    match url.filter(|_| options.display.show_map_links) {
+        Some(url) => Cell::new(format!("{}\n{}", text, url.dimmed().underline())).fg(colour),
+        None => Cell::new(text).fg(colour),
+    }
+}
+
+fn report_footer(report: &FetchReport) -> String {
+    // Out-of-date data is coloured by how old it is; anything else that went wrong is a warning.
+    let severity = report.staleness().map_or(Severity::Warning, |(_, severity)| severity);
+    report.warning().map(|warning| format!("{}\n", severity.paint(&warning))).unwrap_or_default()
+}
+
+// --- Week Display ---
+
+/// The Monday of the week containing `date`.
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// The days of the week containing `target_date` with their events, and what the filters took
+/// out of them. Days past the configured week length (the weekend, by default) are only
+/// included when something is scheduled on them, and the weekend not at all with
+/// `skip_weekends`.
+fn week_days(events: &[Event], target_date: NaiveDate, options: &ViewOptions) -> (Vec<(NaiveDate, Vec<Event>)>, FilterCounts) {
+    let monday = week_start(target_date);
+    let mut counts = FilterCounts::default();
+    let days = (0..7).map(|i| {
+        let date = monday + Duration::days(i);
+        let mut daily_events = day_events(events, date);
+        counts += apply_filters(&mut daily_events, options);
+        (date, daily_events)
+    }).filter(|(date, daily_events)| {
+        let day = date.weekday().num_days_from_monday();
+        !(options.display.skip_weekends && day >= 5) && (day < options.display.week_length || !daily_events.is_empty())
+    }).collect();
+    (days, counts)
+}
+
+/// A week's days, each with its events.
+type WeekDays = Vec<(NaiveDate, Vec<Event>)>;
+
+/// `count` weeks of `week_days`, from the week starting on `monday`.
+fn weeks_days(events: &[Event], monday: NaiveDate, count: usize, options: &ViewOptions) -> (Vec<WeekDays>, FilterCounts) {
+    let mut counts = FilterCounts::default();
+    let weeks = (0..count).map(|week| {
+        let (days, week_counts) = week_days(events, monday + Duration::weeks(week as i64), options);
+        counts += week_counts;
+        days
+    }).collect();
+    (weeks, counts)
+}
+
+/// "Week of 19 October 2026", with the teaching week when the academic calendar knows it.
+fn week_title(days: &[(NaiveDate, Vec<Event>)], options: &ViewOptions) -> String {
+    let monday = week_start(days[0].0);
+    let teaching_week = days.iter().find_map(|(date, _)| options.calendar.week_of(*date)).map(|week| format!(" ({})", week)).unwrap_or_default();
+    format!("Week of {}{}", monday.format("%d %B %Y"), teaching_week)
+}
+
+/// A grid with a column per day.
+fn render_week(days: &[(NaiveDate, Vec<Event>)], options: &ViewOptions, report: &FetchReport) -> String {
+    let mut out = String::new();
+    writeln!(out, " {}", week_title(days, options).bold()).unwrap();
+    out.push_str(&filter_header(options));
+
+    let label_style = options.weekday_labels.unwrap_or(WeekdayLabel::Short);
+    let today = display_now().date_naive();
+    let mut table = Table::new();
+    load_table_preset(&mut table).set_content_arrangement(ContentArrangement::Dynamic).set_width(table_width(options.width));
+    table.set_header(days.iter().map(|(date, _)| {
+        let relative = if *date == today { today_marker().to_string() } else { relative_day_label(*date, today, options.display.week_start) };
+        let cell = Cell::new(format!("{} {}\n{}", weekday_label(*date, label_style), date.format("%d/%m"), relative))
+            .fg(cell_colour(Role::Heading))
+            .bg(day_header_background(*date));
+        if *date == today { cell.add_attribute(Attribute::Bold) } else { cell }
+    }));
+    table.add_row(days.iter().map(|(_, daily_events)| {
+        let entries: Vec<String> = daily_events.iter().map(|event| {
+            let start_time = parse_time(&event.start).unwrap();
+            let end_time = parse_time(&event.end).unwrap();
+            let title = compress_title(&event.title, options.display.density);
+            let title = if is_focused(event) { paint(&title, Role::Heading).bold().to_string() } else { title };
+            format!("{}-{}\n{}\n{}", format_time(start_time, &options.display), format_time(end_time, &options.display), title, short_location(event))
+        }).collect();
+        Cell::new(if entries.is_empty() { "-".to_string() } else { entries.join("\n\n") })
+    }));
+    writeln!(out, "{}", table).unwrap();
+    out.push_str(&report_footer(report));
+    out
+}
+
+/// One line per day listing compressed titles with their start times.
+fn render_compact_week(days: &[(NaiveDate, Vec<Event>)], options: &ViewOptions) -> String {
+    filter_header(options) + &compact_week_lines(days, options)
+}
+
+fn compact_week_lines(days: &[(NaiveDate, Vec<Event>)], options: &ViewOptions) -> String {
+    let label_style = options.weekday_labels.unwrap_or(WeekdayLabel::Short);
+    let today = display_now().date_naive();
+    let mut out = String::new();
+    for (date, daily_events) in days {
+        let entries: Vec<String> = daily_events.iter().map(|event| {
+            let start_time = parse_time(&event.start).unwrap();
+            let title = compress_title(&event.title, options.display.density);
+            let title = if is_focused(event) { paint(&title, Role::Heading).bold().to_string() } else { title };
+            format!("{} {}", paint(&format_time(start_time, &options.display), Role::Time), title)
+        }).collect();
+        let listing = if entries.is_empty() { paint("free", Role::Good).to_string() } else { entries.join(", ") };
+        let marker = if *date == today { format!(" {}", today_marker()) } else { String::new() };
+        writeln!(out, "{}: {}{}", weekday_label(*date, label_style).bold(), listing, marker.bold()).unwrap();
+    }
+    out
+}
+
+/// `--weeks`: each week as in the compact week, followed by its summary, and a total at the end.
+fn render_weeks(weeks: &[WeekDays], options: &ViewOptions) -> String {
+    let mut out = filter_header(options);
+    for days in weeks {
+        writeln!(out, " {}", week_title(days, options).bold()).unwrap();
+        out.push_str(&compact_week_lines(days, options));
+        writeln!(out, "{} {}\n", "Summary:".bold(), week_summary(days)).unwrap();
+    }
+    writeln!(out, "{} {}", "Total:".bold(), weeks_total(weeks)).unwrap();
+    out
+}
+
+/// The weeks as one Markdown document, for pasting into an email.
+fn render_weeks_markdown(weeks: &[WeekDays], options: &ViewOptions) -> String {
+    let mut out = String::new();
+    let first = weeks.first().and_then(|days| days.first()).map(|(date, _)| *date);
+    let last = weeks.last().and_then(|days| days.last()).map(|(date, _)| *date);
+    if let (Some(first), Some(last)) = (first, last) {
+        writeln!(out, "# Timetable, {} to {}\n", first.format("%d %B"), last.format("%d %B %Y")).unwrap();
+    }
+    for days in weeks {
+        writeln!(out, "## {}\n", week_title(days, options)).unwrap();
+        for (date, daily_events) in days {
+            let entries: Vec<String> = daily_events.iter().map(|event| {
+                let start = parse_time(&event.start).map(|start| format_time(start, &options.display)).unwrap_or_default();
+                format!("{} {}", start, compress_title(&event.title, options.display.density))
+            }).collect();
+            writeln!(out, "- **{}:** {}", date.format("%a %d %b"), if entries.is_empty() { "free".to_string() } else { entries.join(", ") }).unwrap();
+        }
+        writeln!(out, "\nSummary: {}\n", week_summary(days)).unwrap();
+    }
+    writeln!(out, "**Total: {}**", weeks_total(weeks)).unwrap();
+    out
+}
+
+// --- Week Statistics ---
+
+/// "9 sessions, 12h, busiest Tue (4h)".
+fn week_summary(days: &[(NaiveDate, Vec<Event>)]) -> String {
+    let minutes = |events: &[Event]| events.iter().map(event_minutes).sum::<i64>();
+    let sessions: usize = days.iter().map(|(_, events)| events.len()).sum();
+    let busiest = days.iter()
+        .map(|(date, events)| (*date, minutes(events)))
+        .max_by_key(|&(date, minutes)| (minutes, std::cmp::Reverse(date)));
+    match busiest {
+        Some((date, busiest_minutes)) if sessions > 0 => {
+            let total: i64 = days.iter().map(|(_, events)| minutes(events)).sum();
+            format!("{} sessions, {}, busiest {} ({})", sessions, format_minutes(total), date.format("%a"), format_minutes(busiest_minutes))
+        }
+        _ => "free".to_string(),
+    }
+}
+
+/// "30 sessions, 36h over 3 weeks".
+fn weeks_total(weeks: &[WeekDays]) -> String {
+    let events: Vec<&Event> = weeks.iter().flatten().flat_map(|(_, events)| events).collect();
+    let minutes: i64 = events.iter().map(|event| event_minutes(event)).sum();
+    format!("{} sessions, {} over {} weeks", events.len(), format_minutes(minutes), weeks.len())
+}
+
+fn event_minutes(event: &Event) -> i64 {
+    match (parse_timestamp(&event.start), parse_timestamp(&event.end)) {
+        (Some(start), Some(end)) => (end - start).num_minutes().max(0),
+        _ => 0,
+    }
+}
+
+fn format_minutes(minutes: i64) -> String {
+    if minutes % 60 == 0 { format!("{}h", minutes / 60) } else { format!("{}h{:02}m", minutes / 60, minutes % 60) }
+}
+
+/// Pairs of events (by index into the sorted `daily_events`) whose times overlap. Events with
+/// implausible times are left out, as they would clash with everything.
+fn find_clashes(daily_events: &[Event]) -> Vec<(usize, usize)> {
+    let times: Vec<_> = daily_events.iter().map(|event| {
+        (parse_timestamp(&event.start).unwrap(), parse_timestamp(&event.end).unwrap())
+    }).collect();
+    let valid: Vec<usize> = (0..daily_events.len()).filter(|&i| daily_events[i].issue.is_none()).collect();
+    let mut clashes = Vec::new();
+    for (n, &i) in valid.iter().enumerate() {
+        for &j in &valid[n + 1..] {
+            // Sorted by start, so `j` starts no earlier than `i`.
+            if times[j].0 < times[i].1 {
+                clashes.push((i, j));
+            }
+        }
+    }
+    clashes
+}
+
+/// The location each event title is most often held in across all fetched events.
+fn usual_locations(events: &[Event]) -> HashMap<&str, &str> {
+    let mut counts: HashMap<&str, HashMap<&str, usize>> = HashMap::new();
+    for event in events {
+        *counts.entry(&event.title).or_default().entry(&event.location).or_default() += 1;
+    }
+    counts.into_iter()
+        .filter_map(|(title, locations)| {
+            // Ties go to the alphabetically first location so the answer is stable.
+            let usual = locations.into_iter().max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))?;
+            Some((title, usual.0))
+        })
+        .collect()
+}
+
+/// When the first event starts and the last one finishes.
+fn day_span(events: &[Event]) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let parse = |t: &str| parse_time(t);
+    let first_start = events.iter().filter_map(|event| parse(&event.start)).min()?;
+    let last_end = events.iter().filter_map(|event| parse(&event.end)).max()?;
+    Some((first_start, last_end))
+}
+
+fn is_assessment(event: &Event) -> bool {
+    let text = format!("{} {}", event.title, event.event_type).to_lowercase();
+    ["exam", "assessment", "test", "coursework", "presentation"].iter().any(|word| text.contains(word))
+}
+
+// --- Shared Free Slots ---
+
+type Interval = (DateTime<FixedOffset>, DateTime<FixedOffset>);
+
+/// Merges overlapping and touching intervals, sweeping through them in order of start.
+fn union_intervals(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.sort();
+    let mut merged: Vec<Interval> = Vec::with_capacity(intervals.len());
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// The gaps of at least `min` between `from` and `until` left by `busy`, which must be merged
+/// and in order.
+fn free_slots(busy: &[Interval], from: DateTime<FixedOffset>, until: DateTime<FixedOffset>, min: Duration) -> Vec<Interval> {
+    let mut slots = Vec::new();
+    let mut cursor = from;
+    for &(start, end) in busy {
+        if start > cursor {
+            slots.push((cursor, start.min(until)));
+        }
+        cursor = cursor.max(end);
+    }
+    if cursor < until {
+        slots.push((cursor, until));
+    }
+    slots.retain(|(start, end)| *end - *start >= min && *end - *start > Duration::zero());
+    slots
+}
+
+/// A person's busy intervals on `date`, from their own config file.
+fn busy_intervals(path: &Path, date: NaiveDate) -> Result<Vec<Interval>, Box<dyn Error + Send + Sync>> {
+    let contents = fs::read_to_string(path).map_err(|e| format!("Could not read '{}': {}.", path.display(), e))?;
+    let config: Config = toml::from_str(&contents).map_err(|e| format!("Invalid config file '{}': {}", path.display(), e))?;
+    let start = date.and_time(NaiveTime::MIN).and_utc() - Duration::days(1);
+    let (events, report) = fetch_range(&config, start, start + Duration::days(3))
+        .map_err(|e| format!("Could not fetch the timetable for '{}': {}", path.display(), e))?;
+    if let Some(warning) = report.warning() {
+        log_warning(format!("{}: {}", path.display(), warning));
+    }
+    Ok(day_events(&events.events, date).iter()
+        .filter_map(|event| Some((parse_time(&event.start)?, parse_time(&event.end)?)))
+        .collect())
+}
+
+fn shared(config: Config, cookies: Vec<PathBuf>, date: Option<NaiveDate>, min_duration: i64, bounds: (&str, &str), format: SharedFormat) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let date = date.unwrap_or_else(display_today);
+    let bound = |time: &str| {
+        let time = NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| format!("Invalid time '{}'; use HH:MM, e.g. 09:00.", time))?;
+        from_display(date.and_time(time)).map(|instant| to_display(&instant)).ok_or_else(|| format!("{} doesn't exist on {}.", time.format("%H:%M"), date))
+    };
+    let (from, until) = (bound(bounds.0)?, bound(bounds.1)?);
+    if until <= from {
+        return Err("--day-end must be after --day-start.".into());
+    }
+    let mut busy = Vec::new();
+    for path in &cookies {
+        busy.extend(busy_intervals(path, date)?);
+    }
+    let slots = free_slots(&union_intervals(busy), from, until, Duration::minutes(min_duration));
+
+    match format {
+        SharedFormat::Json => {
+            let free: Vec<serde_json::Value> = slots.iter().map(|(start, end)| serde_json::json!({
+                "start": start.to_rfc3339(),
+                "end": end.to_rfc3339(),
+                "minutes": (*end - *start).num_minutes(),
+            })).collect();
+            println!("{}", serde_json::to_string_pretty(&serde_json::json!({
+                "date": date.to_string(),
+                "people": cookies.iter().map(|path| path.display().to_string()).collect::<Vec<_>>(),
+                "free": free,
+            }))?);
+        }
+        SharedFormat::Text => {
+            println!(" {} {}", format!("Free for all {} people on", cookies.len()).bold(), date.format("%A, %d %B %Y").to_string().bold());
+            if slots.is_empty() {
+                println!("{}", paint(&format!("No common gap of {} minutes or more.", min_duration), Role::Warning));
+            }
+            for (start, end) in slots {
+                println!("  {}–{}  {}", format_time(start, &config.display), format_time(end, &config.display), format_minutes((end - start).num_minutes()));
+            }
+        }
+    }
+    Ok(())
+}
+
+// --- Weekly Report ---
+
+struct ReportDay {
+    date: NaiveDate,
+    events: Vec<Event>,
+    notes: Vec<String>,
+}
+
+fn time_range(event: &Event, display: &DisplayConfig) -> String {
+    let time = |t: &str| parse_time(t).map(|t| format_time(t, display)).unwrap_or_default();
+    format!("{}-{}", time(&event.start), time(&event.end))
+}
+
+fn report_days(all_events: &[Event], days: Vec<(NaiveDate, Vec<Event>)>, display: &DisplayConfig) -> Vec<ReportDay> {
+    let usual = usual_locations(all_events);
+    days.into_iter().map(|(date, events)| {
+        let mut notes: Vec<String> = find_clashes(&events).into_iter()
+            .map(|(i, j)| format!("Clash: {} ({}) overlaps {} ({})", events[i].title, time_range(&events[i], display), events[j].title, time_range(&events[j], display)))
+            .collect();
+        notes.extend(events.iter().filter_map(|event| {
+            let usual_location = *usual.get(event.title.as_str())?;
+            (usual_location != event.location).then(|| format!("Unusual room: {} is in {} (usually {})", event.title, event.location, usual_location))
+        }));
+        ReportDay { date, events, notes }
+    }).collect()
+}
+
+fn report_subject(days: &[ReportDay]) -> String {
+    let sessions: usize = days.iter().map(|day| day.events.len()).sum();
+    let minutes: i64 = days.iter().flat_map(|day| &day.events).map(event_minutes).sum();
+    format!("Week {}: {} sessions, {}", days[0].date.iso_week().week(), sessions, format_minutes(minutes))
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A colourless, self-contained summary of the week, meant to be mailed as-is.
+fn render_report(days: &[ReportDay], format: ReportFormat, display: &DisplayConfig) -> String {
+    let mut out = String::new();
+    let title = format!("Week of {}", days[0].date.format("%A %d %B %Y"));
+    let summary = |events: &[Event]| match day_span(events) {
+        Some((first_start, last_end)) => format!(
+            "{} sessions, {}, {} to {}",
+            events.len(),
+            format_minutes(events.iter().map(event_minutes).sum()),
+            format_time(first_start, display),
+            format_time(last_end, display),
+        ),
+        None => "free".to_string(),
+    };
+    let assessments: Vec<&Event> = days.iter().flat_map(|day| &day.events).filter(|event| is_assessment(event)).collect();
+    let total = report_subject(days);
+    let total = total.split_once(": ").map(|(_, totals)| totals).unwrap_or(&total);
+
+    match format {
+        ReportFormat::Text | ReportFormat::Markdown => {
+            let markdown = matches!(format, ReportFormat::Markdown);
+            if markdown { writeln!(out, "# {}\n", title).unwrap(); } else { writeln!(out, "{}\n{}\n", title, "=".repeat(title.chars().count())).unwrap(); }
+            for day in days {
+                let heading = format!("{}: {}", day.date.format("%A %d %B"), summary(&day.events));
+                if markdown { writeln!(out, "## {}\n", heading).unwrap(); } else { writeln!(out, "{}", heading).unwrap(); }
+                for event in &day.events {
+                    let bullet = if markdown { "-" } else { " " };
+                    writeln!(out, "{} {}  {} ({}) @ {}", bullet, time_range(event, display), event.title, event.event_type, event.location).unwrap();
+                }
+                for note in &day.notes {
+                    writeln!(out, "{} ! {}", if markdown { "-" } else { " " }, note).unwrap();
+                }
+                writeln!(out).unwrap();
+            }
+            if !assessments.is_empty() {
+                if markdown { writeln!(out, "## Assessments\n").unwrap(); } else { writeln!(out, "Assessments").unwrap(); }
+                for event in &assessments {
+                    let when = parse_time(&event.start).map(|t| format!("{} {}", t.format("%a %d %b"), format_time(t, display))).unwrap_or_default();
+                    writeln!(out, "{} {}  {} @ {}", if markdown { "-" } else { " " }, when, event.title, event.location).unwrap();
+                }
+                writeln!(out).unwrap();
+            }
+            if markdown { writeln!(out, "**Total: {}**", total).unwrap(); } else { writeln!(out, "Total: {}", total).unwrap(); }
+        }
+        ReportFormat::Html => {
+            writeln!(out, "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n<title>{}</title>\n</head>\n<body>", html_escape(&title)).unwrap();
+            writeln!(out, "<h1>{}</h1>", html_escape(&title)).unwrap();
+            for day in days {
+                writeln!(out, "<h2>{}: {}</h2>", day.date.format("%A %d %B"), html_escape(&summary(&day.events))).unwrap();
+                if !day.events.is_empty() || !day.notes.is_empty() {
+                    writeln!(out, "<ul>").unwrap();
+                    for event in &day.events {
+                        writeln!(out, "<li>{} {} ({}) @ {}</li>", time_range(event, display), html_escape(&event.title), html_escape(&event.event_type), html_escape(&event.location)).unwrap();
+                    }
+                    for note in &day.notes {
+                        writeln!(out, "<li><strong>{}</strong></li>", html_escape(note)).unwrap();
+                    }
+                    writeln!(out, "</ul>").unwrap();
+                }
+            }
+            if !assessments.is_empty() {
+                writeln!(out, "<h2>Assessments</h2>\n<ul>").unwrap();
+                for event in &assessments {
+                    let when = parse_time(&event.start).map(|t| format!("{} {}", t.format("%a %d %b"), format_time(t, display))).unwrap_or_default();
+                    writeln!(out, "<li>{} {} @ {}</li>", when, html_escape(&event.title), html_escape(&event.location)).unwrap();
+                }
+                writeln!(out, "</ul>").unwrap();
+            }
+            writeln!(out, "<p><strong>Total: {}</strong></p>\n</body>\n</html>", html_escape(total)).unwrap();
+        }
+    }
+    out
+}
+
+// --- Day Brief ---
+
+/// Consecutive events in different buildings with at most this many minutes between them.
+const TIGHT_TRANSFER_MINUTES: i64 = 10;
+
+/// A dash between consecutive events in different buildings.
+struct TightTransfer {
+    from: Event,
+    to: Event,
+    minutes: i64,
+}
+
+/// What `bstt brief` says about a day.
+struct DayBrief {
+    date: NaiveDate,
+    events: Vec<Event>,
+    /// When the first event starts and the last one finishes.
+    span: Option<Interval>,
+    longest_gap: Option<Interval>,
+    tight_transfers: Vec<TightTransfer>,
+    /// Clashes and unusual rooms, as the weekly report notes them.
+    warnings: Vec<String>,
+    /// Changes to the day's events seen between fetches.
+    changes: Vec<String>,
+}
+
+fn building(event: &Event) -> &str {
+    event.location.split(':').next().unwrap_or_default().trim()
+}
+
+/// Gathers the brief for `date` from its (sorted, filtered) events and everything fetched.
+fn day_brief(all_events: &[Event], date: NaiveDate, events: Vec<Event>, display: &DisplayConfig) -> DayBrief {
+    let span = day_span(&events);
+    let busy: Vec<Interval> = events.iter()
+        .filter(|event| event.issue.is_none())
+        .filter_map(|event| Some((parse_time(&event.start)?, parse_time(&event.end)?)))
+        .collect();
+    let longest_gap = span.and_then(|(first_start, last_end)| {
+        free_slots(&union_intervals(busy), first_start, last_end, Duration::zero()).into_iter().max_by_key(|(start, end)| *end - *start)
+    });
+    let tight_transfers = events.windows(2).filter_map(|pair| {
+        let (from, to) = (&pair[0], &pair[1]);
+        if is_online(from) || is_online(to) || building(from).is_empty() || building(to).is_empty() || building(from) == building(to) {
+            return None;
+        }
+        let minutes = (parse_time(&to.start)? - parse_time(&from.end)?).num_minutes();
+        (0..=TIGHT_TRANSFER_MINUTES).contains(&minutes).then(|| TightTransfer { from: from.clone(), to: to.clone(), minutes })
+    }).collect();
+    let warnings = report_days(all_events, vec![(date, events.clone())], display).pop().map(|day| day.notes).unwrap_or_default();
+    // Only the latest change of each kind to an event, as a room that moved twice is where it
+    // ended up that matters.
+    let mut latest: Vec<ChangeRecord> = Vec::new();
+    for change in load_changes().into_iter().filter(|change| parse_time(&change.start).is_some_and(|start| start.date_naive() == date)) {
+        latest.retain(|earlier| (&earlier.kind, &earlier.title, &earlier.start) != (&change.kind, &change.title, &change.start));
+        latest.push(change);
+    }
+    let changes = latest.into_iter().map(|change| change.message).collect();
+    DayBrief { date, events, span, longest_gap, tight_transfers, warnings, changes }
+}
+
+fn render_brief(brief: &DayBrief, display: &DisplayConfig) -> String {
+    let mut out = String::new();
+    let day = brief.date.format("%A %d %B").to_string();
+    let Some((first_start, last_end)) = brief.span else {
+        writeln!(out, "{}: {}", day.bold(), paint("nothing on.", Role::Good)).unwrap();
+        for change in &brief.changes {
+            writeln!(out, "{}", paint(change, Role::Warning)).unwrap();
+        }
+        return out;
+    };
+    let minutes = brief.events.iter().filter(|event| event.issue.is_none()).map(event_minutes).sum();
+    let sessions = if brief.events.len() == 1 { "1 session".to_string() } else { format!("{} sessions", brief.events.len()) };
+    writeln!(out, "{}: {}, {}", day.bold(), sessions, format_minutes(minutes)).unwrap();
+    let first = &brief.events[0];
+    let location = if is_online(first) { "online".to_string() } else { first.location.clone() };
+    writeln!(out, "First: {} {} in {}", paint(&format_time(first_start, display), Role::Time), compress_title(&first.title, display.density), paint(&location, Role::Location)).unwrap();
+    if let Some((start, end)) = brief.longest_gap {
+        writeln!(out, "Longest gap: {}, {}–{}", format_minutes((end - start).num_minutes()), format_time(start, display), format_time(end, display)).unwrap();
+    }
+    for transfer in &brief.tight_transfers {
+        let at = parse_time(&transfer.to.start).map(|start| format_time(start, display)).unwrap_or_default();
+        let message = format!("Tight: {} minutes from {} to {} for {}", transfer.minutes, building(&transfer.from), building(&transfer.to), at);
+        writeln!(out, "{}", paint(&message, Role::Warning)).unwrap();
+    }
+    for warning in &brief.warnings {
+        writeln!(out, "{}", paint(warning, Role::Warning)).unwrap();
+    }
+    for change in &brief.changes {
+        writeln!(out, "{}", paint(change, Role::Warning)).unwrap();
+    }
+    writeln!(out, "Done at {}.", paint(&format_time(last_end, display), Role::Time)).unwrap();
+    out
+}
+
+fn brief_json(brief: &DayBrief) -> serde_json::Value {
+    let interval = |(start, end): Interval| serde_json::json!({
+        "start": start.to_rfc3339(),
+        "end": end.to_rfc3339(),
+        "minutes": (end - start).num_minutes(),
+    });
+    serde_json::json!({
+        "date": brief.date.to_string(),
+        "sessions": brief.events.len(),
+        "minutes": brief.events.iter().filter(|event| event.issue.is_none()).map(event_minutes).sum::<i64>(),
+        "first": brief.events.first().map(event_json),
+        "done_at": brief.span.map(|(_, last_end)| last_end.to_rfc3339()),
+        "longest_gap": brief.longest_gap.map(interval),
+        "tight_transfers": brief.tight_transfers.iter().map(|transfer| serde_json::json!({
+            "from": building(&transfer.from),
+            "to": building(&transfer.to),
+            "minutes": transfer.minutes,
+            "start": parse_time(&transfer.to.start).map(|start| start.to_rfc3339()),
+        })).collect::<Vec<_>>(),
+        "warnings": brief.warnings,
+        "changes": brief.changes,
+    })
+}
+
+fn brief_command(mut config: Config, day_offset: String, date: Option<NaiveDate>, filters: FilterArgs, json: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let day_offset = date.map_or(day_offset, |date| date.signed_duration_since(display_today()).num_days().to_string());
+    let target_date = target_date(&mut config, &day_offset, false)?;
+    let options = view_options(&config, filters, false, None);
+    let (all_events, _) = fetch_with_spinner(config, false)?;
+    let mut daily_events = day_events(&all_events.events, target_date);
+    apply_filters(&mut daily_events, &options);
+    let brief = day_brief(&all_events.events, target_date, daily_events, &options.display);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&brief_json(&brief))?);
+    } else {
+        print!("{}", render_brief(&brief, &options.display));
+    }
+    Ok(())
+}
+
+// --- Event Details ---
+fn display_event_details(event: &Event, display: &DisplayConfig) {
+    let time_str = match (parse_time(&event.start), parse_time(&event.end)) {
+        (Some(start), Some(end)) => {
+            let minutes = (end - start).num_minutes();
+            format!("{} {} - {} ({}h{:02}m)", start.format("%A, %d %B %Y"), format_time(start, display), format_time(end, display), minutes / 60, minutes % 60)
+        }
+        _ => format!("{} - {}", event.start, event.end),
+    };
+
+    println!(" {}", event.title.bold());
+    println!(" {:<10}{}", "Type", paint(&event.event_type, Role::EventType));
+    println!(" {:<10}{}", "Time", paint(&time_str, Role::Time));
+    let location = if is_online(event) { format!("{} ({})", online_label(), event.location) } else { event.location.clone() };
+    println!(" {:<10}{}", "Location", paint(&location, Role::Location));
+    if let Some(url) = joining_url(event) {
+        println!(" {:<10}{}", "Join", url.underline());
+    } else if let Some(url) = event_map_url(event) {
+        println!(" {:<10}{}", "Map", url.dimmed().underline());
+    }
+    println!(" {:<10}{}", "Lecturer", paint(event.teacher_name.as_deref().unwrap_or(""), Role::Lecturer));
+}
+
+// --- Compression Helpers (Unchanged) ---
+
+/// The rules that changed a text, each with the text just after it, for `bstt compress --explain`.
+type CompressionTrace = Vec<(String, String)>;
+
+fn apply_transformations(mut s: String, rules: &[(&str, &str)], trace: &mut CompressionTrace) -> String {
+    for (find, replace) in rules.iter() {
+        if s.contains(find) {
+            s = s.replace(find, replace);
+            trace.push((format!("{:?} → {:?}", find, replace), s.clone()));
+        }
+    }
+    s
+}
+
+/// `[display] strip_groups`, decided once the config is loaded.
+static STRIP_GROUPS: OnceLock<bool> = OnceLock::new();
+
+/// Shortens a title for narrow views, with the rule sets `density` allows (see `[display] density`).
+fn compress_title(title: &str, density: u8) -> String {
+    compress_title_traced(title, density, &mut Vec::new())
+}
+
+fn compress_title_traced(title: &str, density: u8, trace: &mut CompressionTrace) -> String {
+    if density == 0 {
+        return title.to_string();
+    }
+    let compound_rules = [
+        ("Software Engineering", "SE"), ("Data Structures", "DS"), ("Intro to AI", "AI"),
+        ("Practical Physics-Computing Lecture", "Labs-Comp Lec"), ("Practical Physics-Computing Drop-in", "Labs-Comp DI"),
+        ("Probability & Statistics for Physicists", "Prob+Stats P"), ("Introductory Mathematics for Physics", "Intro M for P"),
+        ("Intro to Coding and Data Analysis", "Coding+D.A."), ("Core Physics I Problem Class", "Core P PrbCls"),
+        ("Intro Mathematics Examples Class", "Intro M ExCls"), ("Practical Physics", "Labs"), ("Problem Class", "PrbCls"),
+    ];
+    let atomic_rules = [
+        ("Introductory", "Intro"), ("Introduction", "Intro"), ("Mathematics", "M"), ("Physics", "P"),
+        ("Probability", "Prob"), ("Statistics", "Stats"), ("Computing", "Comp"),
+        ("Lecture", "Lec"), ("Tutorial", "Tut"), ("Workshop", "W"), ("Project", "Proj"), ("Assembly", "Asmbly"),
+    ];
+    let symbol_rules = [(" and ", " + "), (" & ", " + "), (" for ", " "), (" of ", " "), (" to ", " ")];
+    let mut processed_title = title.to_string();
+    if density >= 3 {
+        processed_title = apply_transformations(processed_title, &compound_rules, trace);
+    }
+    if density >= 2 {
+        processed_title = apply_transformations(processed_title, &atomic_rules, trace);
+    }
+    processed_title = apply_transformations(processed_title, &symbol_rules, trace);
+    let numerals = [" V", " IV", " III", " II", " I"];
+    for num in numerals.iter() {
+        if processed_title.ends_with(num) {
+            processed_title = processed_title[..processed_title.len() - num.len()].to_string();
+            trace.push((format!("trailing {:?}", num.trim()), processed_title.clone()));
+            break;
+        }
+    }
+    if !STRIP_GROUPS.get().copied().unwrap_or(true) {
+        return processed_title;
+    }
+    let words: Vec<&str> = processed_title.split_whitespace().filter(|word| !word.to_lowercase().starts_with("grp")).collect();
+    let compressed = words.join(" ");
+    if compressed != processed_title {
+        trace.push(("dropping \"Grp\" words".to_string(), compressed.clone()));
+    }
+    compressed
+}
+
+fn compress_location(location: &str) -> String {
+    compress_location_traced(location, &mut Vec::new())
+}
+
+fn compress_location_traced(location: &str, trace: &mut CompressionTrace) -> String {
+    let rules = [
+        ("Physics Building", "Phys"), ("Priory Road Complex", "PrioryRd"),
+        ("Biomedical Sciences Building", "BioSci"), ("31-37 St. Michael's Hill", "StMichHill"),
+        ("Queen's Building", "Queens"), ("Chemistry Building", "Chem"), ("Fry Building", "Fry"),
+        ("Lecture Theatre", "LT"), ("Building", "Bldg"), ("Complex", "Cmplx"),
+        (" Room", ""), ("Rear:", ""), (": ", ":"),
+    ];
+    apply_transformations(location.to_string(), &rules, trace)
+}
+
+/// Prints the compressed form of each text, with `explain` also each rule that changed it.
+fn compress_command(display: &DisplayConfig, text: Option<String>, location: bool, explain: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let texts: Vec<String> = match text {
+        Some(text) => vec![text],
+        None => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input).map_err(|e| format!("Could not read standard input: {}", e))?;
+            input.lines().filter(|line| !line.trim().is_empty()).map(str::to_string).collect()
+        }
+    };
+    for text in texts {
+        let mut trace = Vec::new();
+        let compressed = if location { compress_location_traced(&text, &mut trace) } else { compress_title_traced(&text, display.density, &mut trace) };
+        if !explain {
+            println!("{}", compressed);
+            continue;
+        }
+        println!("{}", text.bold());
+        if trace.is_empty() {
+            println!("  {}", "no rules apply".dimmed());
+        }
+        for (rule, after) in trace {
+            println!("  {}  {}", rule.dimmed(), after);
+        }
+        println!("= {}\n", compressed.bold());
+    }
+    Ok(())
+}
+
+// --- Mini-Mode Display (MODIFIED) ---
+
+// Events occupy the half-open interval [start, end): one ending at 10:00 is over at 10:00, the
+// moment the next one starts. A zero-length event is an instant, listed until it passes but
+// never in progress.
+
+fn is_in_progress(event: &Event, now: DateTime<FixedOffset>) -> bool {
+    matches!((parse_time(&event.start), parse_time(&event.end)), (Some(start), Some(end)) if start <= now && now < end)
+}
+
+fn is_over(event: &Event, now: DateTime<FixedOffset>) -> bool {
+    match (parse_time(&event.start), parse_time(&event.end)) {
+        (Some(start), Some(end)) if start == end => start < now,
+        (_, Some(end)) => end <= now,
+        _ => true,
+    }
+}
+
+/// How long before the end of an event the status line starts pointing at the next one.
+const BORDER_MINUTES: u32 = 10;
+
+/// Where the day stands, as the status bars see it.
+#[derive(Debug, Clone, Copy)]
+enum TimetableState<'a> {
+    /// An event is in progress.
+    Current(&'a Event),
+    /// An event is in its last few minutes, and another follows later today.
+    Border { current: &'a Event, next: &'a Event },
+    /// Nothing is in progress, but something is still to come today.
+    Next(&'a Event),
+    /// Nothing is left today.
+    Blank,
+}
+
+impl TimetableState<'_> {
+    /// The name bars can style on (Waybar's `class`).
+    fn name(&self) -> &'static str {
+        match self {
+            TimetableState::Current(_) => "current",
+            TimetableState::Border { .. } => "border",
+            TimetableState::Next(_) => "next",
+            TimetableState::Blank => "blank",
+        }
+    }
+}
+
+/// Classifies the moment `now` against today's (sorted) events. An event counts as current
+/// until `grace_minutes` after its end.
+fn classify_timetable_state(events: &[Event], now: DateTime<FixedOffset>, border_minutes: u32, grace_minutes: i64) -> TimetableState<'_> {
+    let grace = Duration::minutes(grace_minutes);
+    let is_current = |event: &&Event| matches!((parse_time(&event.start), parse_time(&event.end)), (Some(start), Some(end)) if start <= now && now < end + grace);
+    // A suspiciously long event would otherwise hide everything it overlaps.
+    let valid = || events.iter().filter(|event| event.issue.is_none()).filter(is_current);
+    // An event that has started takes over from one that's only current through its grace.
+    let current_event = valid().find(|event| is_in_progress(event, now)).or_else(|| valid().next());
+    // The next upcoming event may be a zero-length one at this very moment.
+    let next_event = events.iter().find(|event| !is_over(event, now) && !is_in_progress(event, now));
+
+    match (current_event, next_event) {
+        (Some(current), Some(next)) => {
+            let border_time = parse_time(&current.end).unwrap() - Duration::minutes(border_minutes as i64);
+            if now >= border_time { TimetableState::Border { current, next } } else { TimetableState::Current(current) }
+        }
+        // The last event of the day stays current through its border.
+        (Some(current), None) => TimetableState::Current(current),
+        (None, Some(next)) => TimetableState::Next(next),
+        (None, None) => TimetableState::Blank,
+    }
+}
+
+/// The single status line for the state.
+fn mini_line(state: TimetableState, display: &DisplayConfig, labels: &MiniConfig) -> String {
+    match state {
+        TimetableState::Current(current) => {
+            let end_time = parse_time(&current.end).unwrap();
+            let current_title = compress_title(&current.title, display.density);
+            let current_loc = short_location(current);
+            format!("{} {}{}{} {} {}", labels.current, current_title, labels.separator, current_loc, labels.end, format_time(end_time, display))
+        }
+        TimetableState::Border { current, next } => {
+            let current_end_str = format_time(parse_time(&current.end).unwrap(), display);
+            let next_start_str = format_time(parse_time(&next.start).unwrap(), display);
+            let next_title = compress_title(&next.title, display.density);
+            let next_loc = short_location(next);
+            format!("{} {}→{}{}{}{}{}", labels.border, current_end_str, next_start_str, labels.separator, next_title, labels.at, next_loc)
+        }
+        TimetableState::Next(next) => {
+            let next_title = compress_title(&next.title, display.density);
+            let next_loc = short_location(next);
+            let next_start = parse_time(&next.start).unwrap();
+            format!("{} {}{}{}{}{}", labels.next, next_title, labels.separator, next_loc, labels.at, format_time(next_start, display))
+        }
+        TimetableState::Blank => labels.blank.clone(),
+    }
+}
+
+/// The unescaped status line for the mini mode.
+fn mini_status(events_data: ApiResponse, report: &FetchReport, display: &DisplayConfig, labels: &MiniConfig, calendar: &AcademicCalendar) -> String {
+    let now = status_now();
+    let mut todays_events = day_events(&events_data.events, now.date_naive());
+    prefer_focus(&mut todays_events);
+    let academic_week = calendar.week_of(now.date_naive());
+    let week = academic_week.map(|week| format!("{}{}", labels.separator, week)).unwrap_or_default();
+    // A trailing `?` flags that part of the timetable couldn't be fetched.
+    let marker = stale_marker(report);
+    let status = match classify_timetable_state(&todays_events, now, BORDER_MINUTES, labels.current_grace_minutes) {
+        // An empty day is expected on a holiday or in a reading week, so say why rather than just BLK.
+        TimetableState::Blank => match (bank_holiday(now.date_naive()), academic_week) {
+            (Some(holiday), _) => format!("{} {}", labels.prefix_holiday, holiday.title),
+            (None, Some(AcademicWeek::Reading { .. })) => labels.prefix_reading_week.clone(),
+            _ => labels.blank.clone(),
+        },
+        state => mini_line(state, display, labels),
+    };
+    format!("{}{}{}", status, week, marker)
+}
+
+// --- Waybar Display ---
+
+/// Escapes text for inclusion in Pango markup.
+fn escape_pango(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Escapes text for Polybar, which reads `%{...}` as formatting tags.
+fn escape_polybar(text: &str) -> String {
+    text.replace('%', "%%")
+}
+
+/// Which bar the status line is for, and so how it has to be escaped.
+#[derive(Debug, Clone, Copy)]
+enum BarOutput {
+    Plain,
+    Polybar,
+    Waybar { markup: bool },
+}
+
+impl BarOutput {
+    /// Escapes finished text (after compression, so that nothing is cut mid-escape).
+    fn escape(self, text: &str) -> String {
+        match self {
+            BarOutput::Plain | BarOutput::Waybar { markup: false } => text.to_string(),
+            BarOutput::Polybar => escape_polybar(text),
+            BarOutput::Waybar { markup: true } => escape_pango(text),
+        }
+    }
+}
+
+fn event_type_colour(event_type: &str) -> &'static str {
+    const DEFAULT_PALETTE: [&str; 6] = ["#89b4fa", "#a6e3a1", "#f9e2af", "#fab387", "#cba6f7", "#94e2d5"];
+    const COLORBLIND_PALETTE: [&str; 6] = ["#56b4e9", "#009e73", "#f0e442", "#e69f00", "#cc79a7", "#0072b2"];
+    let palette = match THEME.get().copied().unwrap_or_default() {
+        Theme::Default => DEFAULT_PALETTE,
+        Theme::Colorblind => COLORBLIND_PALETTE,
+    };
+    match event_type.to_lowercase().as_str() {
+        "lecture" => palette[0],
+        "practical" | "laboratory" => palette[1],
+        "tutorial" => palette[2],
+        "workshop" => palette[3],
+        other => palette[(stable_hash(other) % palette.len() as u64) as usize],
+    }
+}
+
+/// One line per remaining event today. With `markup`, each line is coloured by event type and
+/// the event in progress is bold.
+fn waybar_tooltip(todays_events: &[Event], now: DateTime<FixedOffset>, markup: bool, display: &DisplayConfig) -> String {
+    let lines: Vec<String> = todays_events.iter().filter_map(|event| {
+        let start_time = parse_time(&event.start)?;
+        let end_time = parse_time(&event.end)?;
+        if is_over(event, now) { return None; }
+        let line = format!("{}–{}  {} ({}) @ {}", format_time(start_time, display), format_time(end_time, display), event.title, event.event_type, event.location);
+        if !markup { return Some(line); }
+        let line = format!("<span foreground=\"{}\">{}</span>", event_type_colour(&event.event_type), escape_pango(&line));
+        Some(if is_in_progress(event, now) { format!("<b>{}</b>", line) } else { line })
+    }).collect();
+
+    if lines.is_empty() { "No more events today.".to_string() } else { lines.join("\n") }
+}
+
+fn display_waybar_timetable(events_data: ApiResponse, markup: bool, report: &FetchReport, display: &DisplayConfig, labels: &MiniConfig) {
+    let now = status_now();
+    let mut todays_events = day_events(&events_data.events, now.date_naive());
+    prefer_focus(&mut todays_events);
+    let state = classify_timetable_state(&todays_events, now, BORDER_MINUTES, labels.current_grace_minutes);
+    let mut text = mini_line(state, display, labels);
+    let mut tooltip = waybar_tooltip(&todays_events, now, markup, display);
+    if let Some(warning) = report.warning() {
+        text.push_str(&stale_marker(report));
+        tooltip = format!("{}\n\n{}", tooltip, if markup { escape_pango(&warning) } else { warning });
+    }
+    let output = serde_json::json!({
+        // Waybar reads the text as markup too, unless the module sets `escape`.
+        "text": BarOutput::Waybar { markup }.escape(&text),
+        "tooltip": tooltip,
+        "class": state.name(),
+        "warnings": report.warning().into_iter().collect::<Vec<_>>(),
+    });
+    println!("{}", output);
+}
+
+
+// --- Machine-Readable Output ---
+
+/// Accounts for the events a machine-readable view leaves out, from the same report and filter
+/// counts as the human footers, so that a dashboard can tell a quiet day from a filtered one.
+fn output_meta(total: usize, report: &FetchReport, counts: FilterCounts) -> serde_json::Value {
+    serde_json::json!({
+        "fetched": total,
+        "duplicates": report.duplicates,
+        "unparseable": report.unparseable.len(),
+        "malformed": report.malformed.len(),
+        "in_range": counts.considered,
+        "filtered": {
+            "type": counts.by_type,
+            "search": counts.by_search,
+            "exclude": counts.excluded,
+            "min_duration": counts.too_short,
+        },
+        "merged": counts.merged,
+        "fetched_at": report.fetched_at.map(|time| time.to_rfc3339()),
+        "stale": report.cached_at.is_some(),
+        "warning": report.warning(),
+    })
+}
+
+/// The JSON shape of an event in machine-readable output.
+fn event_json(event: &Event) -> serde_json::Value {
+    let local = |time: &str| parse_time(time).map(|t| t.to_rfc3339()).unwrap_or_else(|| time.to_string());
+    serde_json::json!({
+        "title": event.title,
+        "type": event.event_type,
+        "start": local(&event.start),
+        "end": local(&event.end),
+        "location": event.location,
+        "map_url": event_map_url(event),
+        "online": is_online(event),
+        "join_url": joining_url(event),
+        "lecturer": event.teacher_name,
+        "level": event.level,
+        "link": deep_link(event),
+        "focus": is_focused(event),
+    })
+}
+
+/// Escapes a TEXT property value per RFC 5545.
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+fn ics_time(time: &str) -> String {
+    parse_timestamp(time)
+        .map(|t| t.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string())
+        .unwrap_or_default()
+}
+
+/// A single VEVENT component for the event.
+fn event_vevent(event: &Event) -> String {
+    let mut out = String::from("BEGIN:VEVENT\r\n");
+    writeln!(out, "UID:{}@bstt\r", event_id(event)).unwrap();
+    writeln!(out, "DTSTAMP:{}\r", Utc::now().format("%Y%m%dT%H%M%SZ")).unwrap();
+    writeln!(out, "DTSTART:{}\r", ics_time(&event.start)).unwrap();
+    writeln!(out, "DTEND:{}\r", ics_time(&event.end)).unwrap();
+    writeln!(out, "SUMMARY:{}\r", ics_escape(&event.title)).unwrap();
+    writeln!(out, "LOCATION:{}\r", ics_escape(&event.location)).unwrap();
+    writeln!(out, "DESCRIPTION:{}\r", ics_escape(&event.event_type)).unwrap();
+    out.push_str("END:VEVENT\r\n");
+    out
+}
+
+/// A calendar of the events, for subscribing to.
+fn events_ics(events: &[Event]) -> String {
+    let mut out = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//bstt//Timetable//EN\r\nX-WR-CALNAME:Timetable\r\n");
+    for event in events {
+        out.push_str(&event_vevent(event));
+    }
+    out.push_str("END:VCALENDAR\r\n");
+    out
+}
+
+/// The event as a terminal QR code, for scanning onto a phone's calendar.
+fn render_qr(event: &Event) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let code = qrcode::QrCode::new(event_vevent(event))?;
+    // Terminal cells are about twice as tall as they are wide.
+    Ok(code.render::<char>().quiet_zone(true).module_dimensions(2, 1).build())
+}
+
+fn events_csv(events: &[Event]) -> String {
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n']) { format!("\"{}\"", value.replace('"', "\"\"")) } else { value.to_string() }
+    };
+    let mut out = String::from("title,type,start,end,location,lecturer\n");
+    for event in events {
+        let json = event_json(event);
+        let row: Vec<String> = ["title", "type", "start", "end", "location", "lecturer"]
+            .iter()
+            .map(|key| field(json[key].as_str().unwrap_or("")))
+            .collect();
+        writeln!(out, "{}", row.join(",")).unwrap();
+    }
+    out
+}
+
+const HTML_STYLE: &str = "body { font-family: system-ui, sans-serif; margin: 1em; }
+summary { font-weight: bold; cursor: pointer; margin: 0.5em 0; }
+table { border-collapse: collapse; width: 100%; }
+th, td { padding: 0.3em 0.6em; text-align: left; border-bottom: 1px solid #ccc; }
+td:first-child { white-space: nowrap; }
+a { color: inherit; }";
+
+/// The row background for an event type in HTML output: `[display] html_colors`, or a pale
+/// default. Anything that could break out of the style attribute is dropped.
+fn html_row_colour(event_type: &str, display: &DisplayConfig) -> String {
+    let configured = display.html_colors.get(&event_type.to_lowercase())
+        .map(|colour| colour.chars().filter(|c| c.is_ascii_alphanumeric() || "#(),.% ".contains(*c)).collect());
+    configured.unwrap_or_else(|| match event_type.to_lowercase().as_str() {
+        "lecture" => "#dbeafe",
+        "practical" | "laboratory" => "#dcfce7",
+        "tutorial" => "#fef9c3",
+        "workshop" => "#ffedd5",
+        _ => "#f3f4f6",
+    }.to_string())
+}
+
+/// A self-contained HTML page of the events, a collapsible table per day with rows coloured by
+/// type and titles linking to the CampusM app.
+fn events_html(events: &[Event], display: &DisplayConfig) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"UTF-8\">\n");
+    out.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>Timetable</title>\n");
+    writeln!(out, "<style>\n{}\n</style>\n</head>\n<body>", HTML_STYLE).unwrap();
+    let mut days: Vec<(Option<NaiveDate>, Vec<&Event>)> = Vec::new();
+    for event in events {
+        let date = parse_time(&event.start).map(|start| start.date_naive());
+        match days.last_mut() {
+            Some((day, day_events)) if *day == date => day_events.push(event),
+            _ => days.push((date, vec![event])),
+        }
+    }
+    if days.is_empty() {
+        out.push_str("<p>No events.</p>\n");
+    }
+    for (date, day_events) in days {
+        let heading = date.map(|date| date.format("%A %d %B %Y").to_string()).unwrap_or_else(|| "Unknown date".to_string());
+        writeln!(out, "<details open>\n<summary>{}</summary>\n<table>", heading).unwrap();
+        out.push_str("<tr><th>Time</th><th>Type</th><th>Event</th><th>Location</th><th>Lecturer</th></tr>\n");
+        for event in day_events {
+            let title = match deep_link(event) {
+                Some(url) => format!("<a href=\"{}\">{}</a>", html_escape(&url), html_escape(&event.title)),
+                None => html_escape(&event.title),
+            };
+            writeln!(
+                out,
+                "<tr style=\"background: {}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                html_row_colour(&event.event_type, display),
+                time_range(event, display),
+                html_escape(&event.event_type),
+                title,
+                html_escape(&event.location),
+                html_escape(event.teacher_name.as_deref().unwrap_or("")),
+            ).unwrap();
+        }
+        out.push_str("</table>\n</details>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Strips ANSI escape sequences, for copying coloured output somewhere that would show them raw.
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.clone().next() == Some(']') {
+            // Skip an OSC sequence (a hyperlink) up to its terminator, ESC \ or BEL.
+            let mut previous = c;
+            for c in chars.by_ref() {
+                if c == '\x07' || (previous == '\x1b' && c == '\\') { break; }
+                previous = c;
+            }
+        } else if c == '\x1b' {
+            // Skip the CSI parameters up to and including the final letter.
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() { break; }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(text: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    arboard::Clipboard::new()?.set_text(text)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    Err("bstt was built without clipboard support; rebuild it with `--features clipboard`.".into())
+}
+
+// --- Serve Mode ---
+
+struct ServeCache {
+    events: Vec<Event>,
+    fetched_at: DateTime<FixedOffset>,
+    stale: bool,
+}
+
+/// What the server knows, shared between the request loop and the refreshes.
+#[derive(Default)]
+struct ServeState {
+    cache: Option<ServeCache>,
+    last_error: Option<String>,
+    /// How long the last finished refresh took.
+    last_refresh: Option<std::time::Duration>,
+    /// Ticks that found the previous refresh still running.
+    skipped_ticks: u64,
+    /// The time between ticks, which grows while refreshes are slow.
+    interval: std::time::Duration,
+}
+
+/// Refreshes the events in the background, unless a refresh is already running, in which case
+/// the tick is skipped rather than fetching (and writing) twice at once.
+fn start_refresh(config: &Arc<Config>, state: &Arc<Mutex<ServeState>>, in_flight: &Arc<AtomicBool>) {
+    if in_flight.swap(true, Ordering::SeqCst) {
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.skipped_ticks += 1;
+        log_verbose(format!("Skipping a refresh, as the last is still running ({} skipped so far)", state.skipped_ticks));
+        return;
+    }
+    let (config, state, in_flight) = (Arc::clone(config), Arc::clone(state), Arc::clone(in_flight));
+    thread::spawn(move || {
+        let started = std::time::Instant::now();
+        let result = fetch_events(&config);
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.last_refresh = Some(started.elapsed());
+        match result {
+            Ok((mut data, report)) => {
+                log_verbose(format!("Refreshed {} events in {:.1}s", data.events.len(), started.elapsed().as_secs_f64()));
+                merge_imported(&mut data.events);
+                drop_short_events(&mut data.events, config.filter.min_event_duration_minutes);
+                if let Some(previous) = &state.cache {
+                    track_changes(&config.changes, &config.display, &previous.events, &data.events, report.window_end);
+                }
+                state.cache = Some(ServeCache { events: data.events, fetched_at: display_now(), stale: false });
+                state.last_error = None;
+            }
+            Err(e) => {
+                log_warning(format!("Refresh failed after {:.1}s: {}", started.elapsed().as_secs_f64(), e));
+                state.last_error = Some(e.to_string());
+                if let Some(cached) = state.cache.as_mut() { cached.stale = true; }
+            }
+        }
+        drop(state);
+        if let Err(e) = notify_changes(&config.changes) {
+            log_warning(format!("Could not notify about timetable changes: {}", e));
+        }
+        in_flight.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Starts a refresh every `interval`. While refreshes take longer than that, the wait doubles
+/// (up to eight times), and it goes back once one is quick again.
+fn schedule_refreshes(config: Arc<Config>, state: Arc<Mutex<ServeState>>, interval: std::time::Duration) {
+    let in_flight = Arc::new(AtomicBool::new(false));
+    thread::spawn(move || {
+        let mut slow_streak = 0;
+        loop {
+            start_refresh(&config, &state, &in_flight);
+            let wait = interval * 2u32.pow(slow_streak);
+            state.lock().unwrap_or_else(PoisonError::into_inner).interval = wait;
+            thread::sleep(wait);
+            let last_refresh = state.lock().unwrap_or_else(PoisonError::into_inner).last_refresh;
+            let slow = in_flight.load(Ordering::SeqCst) || last_refresh.is_some_and(|took| took > interval);
+            slow_streak = if slow { (slow_streak + 1).min(3) } else { 0 };
+            if slow_streak > 0 {
+                log_verbose(format!("Refreshes are slow; waiting {}s before the next", (interval * 2u32.pow(slow_streak)).as_secs()));
+            }
+        }
+    });
+}
+
+/// An ETag for the events, which changes whenever any of them does.
+fn events_etag(events: &[Event]) -> String {
+    let mut ids: Vec<String> = events.iter()
+        .map(|event| format!("{}|{}|{}|{}", event_id(event), event.location, event.event_type, event.teacher_name.as_deref().unwrap_or("")))
+        .collect();
+    ids.sort();
+    format!("\"{:016x}\"", stable_hash(&ids.join("\n")))
+}
+
+fn json_response(status: u16, body: serde_json::Value) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    let header = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+    tiny_http::Response::from_string(body.to_string()).with_status_code(status).with_header(header)
+}
+
+/// The host to put in URLs for a server bound to `bind`.
+fn serve_host(bind: &str) -> &str {
+    match bind {
+        "0.0.0.0" | "::" => "localhost",
+        host => host,
+    }
+}
+
+/// Starts the same command again in the background, detached from the terminal. Its
+/// diagnostics go to `[log] file`, if one is configured.
+fn daemonize(address: &ServeAddress) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Fail here, rather than silently in the background, if the port is taken.
+    drop(std::net::TcpListener::bind((address.bind.as_str(), address.port))
+        .map_err(|e| format!("Could not listen on {}:{}: {}.", address.bind, address.port, e))?);
+    let args: Vec<std::ffi::OsString> = env::args_os().skip(1).filter(|arg| arg != "--daemonize").collect();
+    let mut command = std::process::Command::new(env::current_exe()?);
+    command.args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // Out of the terminal's process group, so that closing it doesn't stop the server.
+        command.process_group(0);
+    }
+    let child = command.spawn()?;
+    eprintln!("Serving the timetable on http://{}:{}/ in the background (PID {}).", serve_host(&address.bind), address.port, child.id());
+    Ok(())
+}
+
+/// Serves `GET /?date=YYYY-MM-DD` (today by default) as JSON, `GET /calendar.ics` as a
+/// calendar of every fetched event and `GET /healthz` as the state of the refreshes, which run
+/// in the background every `refresh_interval`. When the API is down, the last good data is
+/// served, with `"stale": true` in the JSON. The calendar has an ETag, so that calendar apps
+/// polling it get a 304 until something changes.
+fn serve(config: Config, address: &ServeAddress, refresh_interval: Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let server = tiny_http::Server::http((address.bind.as_str(), address.port))
+        .map_err(|e| format!("Could not listen on {}:{}: {}.", address.bind, address.port, e))?;
+    eprintln!("Serving the timetable on http://{}:{}/", serve_host(&address.bind), address.port);
+    let interval = refresh_interval.to_std().ok().filter(|interval| !interval.is_zero()).ok_or("--cache-interval must be at least a second.")?;
+    let state = Arc::new(Mutex::new(ServeState { interval, ..Default::default() }));
+    let token = config.serve.token.clone().filter(|token| !token.is_empty());
+    schedule_refreshes(Arc::new(config), Arc::clone(&state), interval);
+
+    for request in server.incoming_requests() {
+        let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+        if *request.method() != tiny_http::Method::Get || !["/", "/calendar.ics", "/healthz"].contains(&path) {
+            let _ = request.respond(json_response(404, serde_json::json!({ "error": "Only GET /, GET /calendar.ics and GET /healthz are supported." })));
+            continue;
+        }
+        // The health check stays open, so that monitors don't need the token.
+        if let Some(token) = token.as_deref().filter(|_| path != "/healthz") {
+            if !query.split('&').any(|pair| pair.strip_prefix("token=") == Some(token)) {
+                let _ = request.respond(json_response(401, serde_json::json!({ "error": "A valid ?token= is required." })));
+                continue;
+            }
+        }
+        let state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        if path == "/healthz" {
+            let healthy = state.cache.as_ref().is_some_and(|cached| !cached.stale);
+            let response = json_response(if healthy { 200 } else { 503 }, serde_json::json!({
+                "ok": healthy,
+                "fetched_at": state.cache.as_ref().map(|cached| cached.fetched_at.to_rfc3339()),
+                "stale": state.cache.as_ref().is_some_and(|cached| cached.stale),
+                "last_error": state.last_error,
+                "last_refresh_ms": state.last_refresh.map(|took| took.as_millis() as u64),
+                "skipped_ticks": state.skipped_ticks,
+                "interval_seconds": state.interval.as_secs(),
+            }));
+            drop(state);
+            let _ = request.respond(response);
+            continue;
+        }
+        let calendar = path == "/calendar.ics";
+        let date_param = query.split('&').find_map(|pair| pair.strip_prefix("date="));
+        let target_date = match date_param.map(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d")) {
+            None => display_today(),
+            Some(Ok(date)) => date,
+            Some(Err(_)) => {
+                let _ = request.respond(json_response(400, serde_json::json!({ "error": "The date must be formatted as YYYY-MM-DD." })));
+                continue;
+            }
+        };
+
+        let response = match &state.cache {
+            Some(cached) if calendar => {
+                let etag = events_etag(&cached.events);
+                let unchanged = request.headers().iter()
+                    .any(|header| header.field.equiv("If-None-Match") && header.value.as_str().split(',').any(|tag| tag.trim() == etag));
+                let etag_header = tiny_http::Header::from_bytes("ETag", etag.as_bytes()).unwrap();
+                if unchanged {
+                    tiny_http::Response::from_string("").with_status_code(304).with_header(etag_header)
+                } else {
+                    let header = tiny_http::Header::from_bytes("Content-Type", "text/calendar; charset=utf-8").unwrap();
+                    tiny_http::Response::from_string(events_ics(&cached.events)).with_header(header).with_header(etag_header)
+                }
+            }
+            Some(cached) => {
+                let daily_events = day_events(&cached.events, target_date);
+                json_response(200, serde_json::json!({
+                    "date": target_date.to_string(),
+                    "fetched_at": cached.fetched_at.to_rfc3339(),
+                    "stale": cached.stale,
+                    "events": daily_events.iter().map(event_json).collect::<Vec<_>>(),
+                }))
+            }
+            None if state.last_error.is_some() => json_response(502, serde_json::json!({ "error": "The timetable could not be fetched." })),
+            None => json_response(503, serde_json::json!({ "error": "The timetable is still being fetched; try again shortly." })),
+        };
+        drop(state);
+        let _ = request.respond(response);
+    }
+    Ok(())
+}
+
+// --- Notifications ---
+
+/// An event `bstt notify` should keep quiet about until it starts.
+#[derive(Serialize, Deserialize, Debug)]
+struct Snoozed {
+    key: String,
+    expires: DateTime<Utc>,
+}
+
+fn snooze_key(event: &Event) -> String {
+    format!("{}|{}", event.title, event.start)
+}
+
+/// The snoozed events that haven't started yet.
+fn load_snoozed() -> Vec<Snoozed> {
+    let snoozed: Vec<Snoozed> = fs::read_to_string(data_dir().join(SNOOZED_FILE)).ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default();
+    snoozed.into_iter().filter(|snoozed| snoozed.expires > Utc::now()).collect()
+}
+
+/// Notifies about today's events starting within `before` minutes, except snoozed ones.
+fn notify(config: Config, before: i64, filters: FilterArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let options = view_options(&config, filters, false, None);
+    let changes = config.changes.clone();
+    let (all_events, _) = fetch_with_spinner(config, true)?;
+    notify_changes(&changes)?;
+    let mut upcoming = day_events(&all_events.events, display_today());
+    apply_filters(&mut upcoming, &options);
+    let now = display_now();
+    let snoozed = load_snoozed();
+    for event in upcoming {
+        let Some(start) = parse_time(&event.start) else { continue };
+        if start <= now || start - now > Duration::minutes(before) || snoozed.iter().any(|snoozed| snoozed.key == snooze_key(&event)) {
+            continue;
+        }
+        send_notification(&event.title, &format!("{} at {}", event.location, format_time(start, &options.display)))?;
+    }
+    Ok(())
+}
+
+fn send_notification(summary: &str, body: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let status = std::process::Command::new("notify-send").args(["--app-name=bstt", summary, body]).status()
+        .map_err(|e| format!("Could not run notify-send: {}", e))?;
+    if !status.success() {
+        return Err(format!("notify-send failed ({}).", status).into());
+    }
+    Ok(())
+}
+
+fn snooze(config: Config, event: &str, args: NumberedViewArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let display = config.display.clone();
+    let now = display_now();
+    let found = match event.parse::<usize>() {
+        Ok(number) => {
+            let (events, view) = numbered_view(config, args)?;
+            last_view_event(&events, &view, number)?.clone()
+        }
+        Err(_) => fetch_with_spinner(config, false)?.0.events.into_iter()
+            .filter(|candidate| candidate.title.to_lowercase().contains(&event.to_lowercase()))
+            .filter(|candidate| parse_time(&candidate.start).is_some_and(|start| start > now))
+            .min_by_key(|candidate| parse_time(&candidate.start))
+            .ok_or_else(|| format!("No upcoming event's title contains '{}'.", event))?,
+    };
+    let start = parse_time(&found.start).ok_or("That event has no readable start time.")?;
+    if start <= now {
+        return Err("That event has already started.".into());
+    }
+    let mut snoozed = load_snoozed();
+    snoozed.push(Snoozed { key: snooze_key(&found), expires: start.to_utc() });
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    write_atomically(&dir.join(SNOOZED_FILE), serde_json::to_string(&snoozed)?.as_bytes())?;
+    eprintln!("Snoozed {} on {} at {}.", found.title, start.format("%a %d %b"), format_time(start, &display));
+    Ok(())
+}
+
+// --- Change Tracking ---
+
+/// A change to an upcoming event between one fetch and the next, as a line of `changes.jsonl`.
+#[derive(Serialize, Deserialize, Debug)]
+struct ChangeRecord {
+    detected_at: DateTime<Utc>,
+    /// "room", "time", "cancelled" or "added".
+    kind: String,
+    title: String,
+    /// When the event starts, or started before it was cancelled.
+    start: String,
+    was: Option<String>,
+    now: Option<String>,
+    message: String,
+    /// Identifies the change, so that it's logged (and notified) once however many fetches see it.
+    key: String,
+}
+
+/// Matches an event across fetches: by its CampusM id when it has one, which survives a move to
+/// another time, or else by `event_id`.
+fn change_key(event: &Event) -> String {
+    event.booking_id.clone().unwrap_or_else(|| event_id(event))
+}
+
+fn change_record(kind: &str, event: &Event, was: Option<String>, now: Option<String>, message: String) -> ChangeRecord {
+    let key = format!("{:016x}", stable_hash(&format!("{}|{}|{}|{}", kind, change_key(event), was.as_deref().unwrap_or(""), now.as_deref().unwrap_or(""))));
+    ChangeRecord { detected_at: Utc::now(), kind: kind.to_string(), title: event.title.clone(), start: event.start.clone(), was, now, message, key }
+}
+
+/// "Thu 10:00–11:00", for saying when an event is.
+fn change_span(event: &Event, display: &DisplayConfig) -> String {
+    match (parse_time(&event.start), parse_time(&event.end)) {
+        (Some(start), Some(end)) => format!("{} {}–{}", start.format("%a"), format_time(start, display), format_time(end, display)),
+        _ => event.start.clone(),
+    }
+}
+
+/// How the events starting between `from` and `until` differ between two fetches.
+fn detect_changes(previous: &[Event], current: &[Event], from: DateTime<FixedOffset>, until: DateTime<FixedOffset>, display: &DisplayConfig) -> Vec<ChangeRecord> {
+    let relevant = |event: &Event| parse_time(&event.start).is_some_and(|start| start >= from && start < until);
+    let label = |event: &Event| match parse_time(&event.start) {
+        Some(start) => format!("{} {} {}", start.format("%a"), format_time(start, display), compress_title(&event.title, display.density)),
+        None => compress_title(&event.title, display.density),
+    };
+    let before: HashMap<String, &Event> = previous.iter().map(|event| (change_key(event), event)).collect();
+    let after: HashMap<String, &Event> = current.iter().map(|event| (change_key(event), event)).collect();
+    let mut changes = Vec::new();
+    for event in current {
+        let Some(was) = before.get(&change_key(event)) else {
+            if relevant(event) {
+                let message = format!("New event: {} in {}", label(event), compress_location(&event.location));
+                changes.push(change_record("added", event, None, Some(event.location.clone()), message));
+            }
+            continue;
+        };
+        if !relevant(event) && !relevant(was) {
+            continue;
+        }
+        if was.start != event.start || was.end != event.end {
+            let (old, new) = (change_span(was, display), change_span(event, display));
+            let message = format!("Time change: {} now {}, was {}", compress_title(&event.title, display.density), new, old);
+            changes.push(change_record("time", event, Some(old), Some(new), message));
+        }
+        if was.location != event.location {
+            let message = format!("Room change: {} now in {}, was {}", label(event), compress_location(&event.location), compress_location(&was.location));
+            changes.push(change_record("room", event, Some(was.location.clone()), Some(event.location.clone()), message));
+        }
+    }
+    for event in previous.iter().filter(|event| !after.contains_key(&change_key(event)) && relevant(event)) {
+        changes.push(change_record("cancelled", event, Some(event.location.clone()), None, format!("Cancelled: {}", label(event))));
+    }
+    changes
+}
+
+fn load_changes() -> Vec<ChangeRecord> {
+    fs::read_to_string(data_dir().join(CHANGES_FILE)).unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Appends the changes that aren't in the log yet, returning how many there were.
+fn record_changes(changes: Vec<ChangeRecord>) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let logged: std::collections::HashSet<String> = load_changes().into_iter().map(|change| change.key).collect();
+    let fresh: Vec<ChangeRecord> = changes.into_iter().filter(|change| !logged.contains(&change.key)).collect();
+    if fresh.is_empty() {
+        return Ok(0);
+    }
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    let mut lines = String::new();
+    for change in &fresh {
+        lines.push_str(&serde_json::to_string(change)?);
+        lines.push('\n');
+    }
+    fs::OpenOptions::new().create(true).append(true).open(dir.join(CHANGES_FILE))?.write_all(lines.as_bytes())?;
+    Ok(fresh.len())
+}
+
+/// Logs how the events in the next `[changes] days` differ from the previous fetch's, up to
+/// `covered_until`, beyond which the fetches can't be compared.
+fn track_changes(config: &ChangesConfig, display: &DisplayConfig, previous: &[Event], current: &[Event], covered_until: DateTime<Utc>) {
+    if config.days <= 0 {
+        return;
+    }
+    let now = display_now();
+    let until = (now + Duration::days(config.days)).min(to_display(&covered_until));
+    match record_changes(detect_changes(previous, current, now, until, display)) {
+        Ok(0) => {}
+        Ok(count) => log_verbose(format!("Logged {} timetable changes", count)),
+        Err(e) => log_warning(format!("Could not log timetable changes: {}", e)),
+    }
+}
+
+/// Sends a desktop notification for each change logged since the last were sent, unless its
+/// event has already started.
+fn notify_changes(config: &ChangesConfig) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !config.notify {
+        return Ok(());
+    }
+    let path = data_dir().join(CHANGES_NOTIFIED_FILE);
+    let since: Option<DateTime<Utc>> = fs::read_to_string(&path).ok().and_then(|contents| serde_json::from_str(&contents).ok());
+    let pending: Vec<ChangeRecord> = load_changes().into_iter().filter(|change| since.is_none_or(|since| change.detected_at > since)).collect();
+    let Some(latest) = pending.last().map(|change| change.detected_at) else { return Ok(()) };
+    let now = display_now();
+    for change in pending.iter().filter(|change| parse_time(&change.start).is_some_and(|start| start > now)) {
+        send_notification("Timetable change", &change.message)?;
+    }
+    write_atomically(&path, serde_json::to_string(&latest)?.as_bytes())?;
+    Ok(())
+}
+
+fn changes_command(display: &DisplayConfig, limit: usize) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let changes = load_changes();
+    if changes.is_empty() {
+        eprintln!("No timetable changes have been logged yet.");
+        return Ok(());
+    }
+    for change in &changes[changes.len().saturating_sub(limit)..] {
+        let detected = to_display(&change.detected_at);
+        println!("{} {}  {}", detected.format("%a %d %b"), format_time(detected, display), change.message);
+    }
+    Ok(())
+}
+
+// --- Focus ---
+
+/// The unit `bstt focus` (or `[display] focus`) picked out.
+static FOCUS: OnceLock<String> = OnceLock::new();
+
+/// Where the focused unit is kept. Each profile has its own.
+fn focus_path(profile: Option<&str>) -> PathBuf {
+    data_dir().join(match profile {
+        Some(profile) => format!("focus.{}.json", profile),
+        None => FOCUS_FILE.to_string(),
+    })
+}
+
+fn load_focus(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().and_then(|contents| serde_json::from_str(&contents).ok())
+}
+
+/// The words of the title at full compression, lowercased, so that "Software Engineering" and
+/// "SE" come out the same.
+fn unit_words(title: &str) -> Vec<String> {
+    compress_title(title, 3).to_lowercase().split_whitespace().map(str::to_string).collect()
+}
+
+/// Whether the title is for `unit`: it contains the unit as written, or its compressed form has
+/// every word of the unit's, so that "Intro to Comp" is a "Computing" event.
+fn title_matches_unit(title: &str, unit: &str) -> bool {
+    if title.to_lowercase().contains(&unit.to_lowercase()) {
+        return true;
+    }
+    let title_words = unit_words(title);
+    let unit_words = unit_words(unit);
+    !unit_words.is_empty() && unit_words.iter().all(|word| title_words.contains(word))
+}
+
+fn is_focused(event: &Event) -> bool {
+    FOCUS.get().is_some_and(|unit| title_matches_unit(&event.title, unit))
+}
+
+/// Puts focused events first among those starting at the same time, so that the status bars
+/// pick them when two clash.
+fn prefer_focus(todays_events: &mut [Event]) {
+    todays_events.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| is_focused(b).cmp(&is_focused(a))));
+}
+
+fn focus_command(config: &Config, profile: Option<&str>, unit: Option<String>, clear: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let path = focus_path(profile);
+    if clear {
+        match fs::remove_file(&path) {
+            Ok(()) => eprintln!("No longer focusing on a unit."),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => eprintln!("No unit was in focus."),
+            Err(e) => return Err(format!("Could not remove '{}': {}.", path.display(), e).into()),
+        }
+        return Ok(());
+    }
+    let Some(unit) = unit.map(|unit| unit.trim().to_string()).filter(|unit| !unit.is_empty()) else {
+        match &config.display.focus {
+            Some(unit) => println!("{}", unit),
+            None => eprintln!("No unit is in focus; pick one with `bstt focus <UNIT>`."),
+        }
+        return Ok(());
+    };
+    let dir = data_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create data directory at '{}': {}.", dir.display(), e))?;
+    write_atomically(&path, serde_json::to_string(&unit)?.as_bytes())?;
+    eprintln!("Focusing on {}.", unit);
+    Ok(())
+}
+
+// --- Alarm ---
+
+/// Quotes `text` as a single word for `sh`.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Prints when to wake up for tomorrow's first (filtered) event, and with `set` runs the
+/// configured command to set an alarm. Exits with status 1 when nothing is on tomorrow.
+fn alarm(config: Config, lead: Option<String>, set: bool, filters: FilterArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let lead_text = lead.unwrap_or_else(|| config.alarm.lead.clone());
+    let lead = parse_duration(&lead_text).filter(|lead| *lead >= Duration::zero())
+        .ok_or_else(|| format!("Invalid lead time '{}'; write it like 75m or 1h30m.", lead_text))?;
+    let command = config.alarm.command.clone();
+    let options = view_options(&config, filters, false, None);
+    let (all_events, _) = fetch_with_spinner(config, false)?;
+    let mut tomorrow = day_events(&all_events.events, display_today() + Duration::days(1));
+    apply_filters(&mut tomorrow, &options);
+    let Some((first, start)) = tomorrow.iter().filter_map(|event| Some((event, parse_time(&event.start)?))).min_by_key(|(_, start)| *start) else {
+        println!("No events tomorrow.");
+        std::process::exit(1);
+    };
+    // An event just after midnight can leave less than the lead time; ring now rather than in the past.
+    let now = display_now();
+    let wake = (start - lead).max(now);
+    println!("Wake up at {} for {} at {} ({})", paint(&format_time(wake, &options.display), Role::Time).bold(), first.title, format_time(start, &options.display), first.location);
+    if wake > start - lead {
+        log_warning(format!("That's less than {} before it starts.", lead_text));
+    }
+    if set {
+        let template = command.ok_or("Set `[alarm] command` to use --set, e.g. command = \"termux-notification --content {title} --when {datetime}\".")?;
+        let command = template
+            .replace("{time}", &shell_quote(&wake.format("%H:%M").to_string()))
+            .replace("{datetime}", &shell_quote(&wake.to_rfc3339()))
+            .replace("{title}", &shell_quote(&first.title));
+        log_verbose(format!("Running: {}", command));
+        let status = std::process::Command::new("sh").args(["-c", &command]).status()
+            .map_err(|e| format!("Could not run the alarm command: {}", e))?;
+        if !status.success() {
+            return Err(format!("The alarm command failed ({}).", status).into());
+        }
+    }
+    Ok(())
+}
+
+// --- Main Execution ---
+fn fetch_with_spinner(config: Config, quiet: bool) -> Result<(ApiResponse, FetchReport), Box<dyn Error + Send + Sync>> {
+    let min_minutes = config.filter.min_event_duration_minutes;
+    if let Some((mut events, report)) = fresh_cached_events(&config) {
+        merge_imported(&mut events.events);
+        apply_date_range(&mut events.events);
+        drop_short_events(&mut events.events, min_minutes);
+        return Ok((events, report));
+    }
+    let quiet = quiet || !std::io::stderr().is_terminal();
+    let spinner = ProgressBar::new_spinner();
+    let ticks: &[&str] = if ascii_only() { &["-", "\\", "|", "/"] } else { &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"] };
+    spinner.set_style(ProgressStyle::default_spinner().tick_strings(ticks).template("{spinner:.blue} {msg}")?);
+    if !quiet { spinner.set_message("Fetching timetable..."); }
+    let cache_format = config.cache.format;
+    let (changes, display) = (config.changes.clone(), config.display.clone());
+    let config_clone = Arc::new(config);
+    let handle = thread::spawn(move || fetch_events(&config_clone));
+    if !quiet {
+        while !handle.is_finished() {
+            spinner.tick();
+            thread::sleep(std::time::Duration::from_millis(50));
+        }
+    }
+    let mut result = match handle.join().unwrap() {
+        Ok((events, report)) => {
+            if let Ok((previous, _)) = load_cache() {
+                let covered_until = report.window_end.min(DateTime::from_timestamp(previous.window_end, 0).unwrap_or_default());
+                let previous: Vec<Event> = previous.events.into_iter().map(Event::from).collect();
+                track_changes(&changes, &display, &previous, &events.events, covered_until);
+            }
+            if let Err(e) = save_cache(cache_format, &events.events, &report) {
+                log_warning(format!("Could not save the cache: {}", e));
+            }
+            Ok((events, report))
+        }
+        // Only an unreachable API falls back to the cache; a --strict failure shouldn't.
+        Err(e) if e.is::<FetchError>() => cached_events().map_err(|cache_error| {
+            log_verbose(format!("No usable cache: {}", cache_error));
+            e
+        }),
+        Err(e) => Err(e),
+    };
+    if let Ok((events, _)) = &mut result {
+        merge_imported(&mut events.events);
+        apply_date_range(&mut events.events);
+        drop_short_events(&mut events.events, min_minutes);
+    }
+    if let Ok((_, report)) = &result {
+        if !report.unparseable.is_empty() {
+            log_warning(format!("{} events skipped due to unparseable times.", report.unparseable.len()));
+        }
+        for event in &report.unparseable {
+            log_verbose(format!("Skipped {:?}: start {:?}, end {:?}", event.title, event.start, event.end));
+        }
+        if !report.malformed.is_empty() {
+            log_warning(format!("{} events skipped because they couldn't be read:\n  {}", report.malformed.len(), report.malformed.join("\n  ")));
+        }
+    }
+    if !quiet {
+        spinner.finish_with_message(outcome_mark(result.is_ok()).to_string());
+    }
+    result
+}
+
+/// Stretches the fetch window to include the days from `first` to `last`, with a day to spare
+/// for events running over midnight.
+fn extend_window_to(api: &mut ApiConfig, first: NaiveDate, last: NaiveDate) {
+    let today = display_today();
+    api.fetch_days_past = api.fetch_days_past.max(today.signed_duration_since(first).num_days() + 1);
+    api.fetch_days_future = api.fetch_days_future.max(last.signed_duration_since(today).num_days() + 1);
+}
+
+/// A bulleted list under a heading for each day.
+fn events_markdown(events: &[Event], display: &DisplayConfig) -> String {
+    let mut out = String::new();
+    let mut current_day = None;
+    for event in events {
+        let day = parse_time(&event.start).map(|start| start.date_naive());
+        if day != current_day || out.is_empty() {
+            let heading = day.map(|day| day.format("%A %d %B %Y").to_string()).unwrap_or_default();
+            writeln!(out, "{}## {}\n", if out.is_empty() { "" } else { "\n" }, heading).unwrap();
+            current_day = day;
+        }
+        writeln!(out, "- {}  {} ({}) @ {}", time_range(event, display), event.title, event.event_type, event.location).unwrap();
+    }
+    if out.is_empty() {
+        out.push_str("No events.\n");
+    }
+    out
+}
+
+/// Renders events for scripts. With `meta`, JSON is an object of it and the events rather than
+/// a bare list; the other formats have nowhere to put it.
+fn render_structured(format: ExportFormat, events: &[Event], display: &DisplayConfig, meta: Option<&serde_json::Value>) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let events_json = events.iter().map(event_json).collect::<Vec<_>>();
+    Ok(match format {
+        ExportFormat::Json => match meta {
+            Some(meta) => format!("{}\n", serde_json::to_string_pretty(&serde_json::json!({ "meta": meta, "events": events_json }))?),
+            None => format!("{}\n", serde_json::to_string_pretty(&events_json)?),
+        },
+        ExportFormat::Csv => events_csv(events),
+        ExportFormat::Html => events_html(events, display),
+        ExportFormat::Ics => events_ics(events),
+        ExportFormat::Markdown => events_markdown(events, display),
+    })
+}
+
+fn report(mut config: Config, day_offset: Option<String>, format: ReportFormat, subject: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let today = display_today();
+    let target_date = match day_offset {
+        Some(offset) => today + Duration::days(offset.parse().map_err(|_| "Invalid day offset.")?),
+        // At the weekend, the coming week is next week.
+        None if today.weekday().num_days_from_monday() >= 5 => today + Duration::days(7),
+        None => today,
+    };
+    extend_window_to(&mut config.api, week_start(target_date), week_start(target_date) + Duration::days(6));
+    let options = ViewOptions { display: config.display.clone(), ..Default::default() };
+    let (all_events, _) = fetch_with_spinner(config, true)?;
+    let days = report_days(&all_events.events, week_days(&all_events.events, target_date, &options).0, &options.display);
+    if subject {
+        println!("{}", report_subject(&days));
+    } else {
+        print!("{}", render_report(&days, format, &options.display));
+    }
+    Ok(())
+}
+
+/// Row `number` of the last numbered view.
+fn last_view_event<'a>(events: &'a [Event], view: &LastView, number: usize) -> Result<&'a Event, Box<dyn Error + Send + Sync>> {
+    let id = number.checked_sub(1).and_then(|i| view.ids.get(i))
+        .ok_or_else(|| format!("There is no event #{} in the last view (it had {} rows).", number, view.ids.len()))?;
+    Ok(events.iter().find(|event| event_id(event) == *id)
+        .ok_or("That event is no longer in the timetable; please re-run bstt first.")?)
+}
+
+/// The fetched events and the last numbered view, once it's checked to be the view `args`
+/// describe: the same day, filters and order, which its row numbers depend on.
+fn numbered_view(mut config: Config, args: NumberedViewArgs) -> Result<(Vec<Event>, LastView), Box<dyn Error + Send + Sync>> {
+    let view = load_last_view()?;
+    let today = display_today();
+    let mut target_date = target_date(&mut config, &args.day_offset, false)?;
+    let offset = target_date.signed_duration_since(today).num_days();
+    let teaching_days = (config.display.teaching_days || args.teaching_day) && !args.calendar_day && offset != 0;
+    let week_length = config.display.week_length;
+    if teaching_days {
+        extend_for_teaching_days(&mut config.api, offset, week_length);
+    }
+    config.display.pinned.extend(args.pin);
+    let hide_finished = (config.display.hide_finished || args.no_prev) && !args.prev;
+    let mut options = view_options(&config, args.filters, false, None);
+    options.merge_adjacent = args.merge_adjacent;
+    options.reverse = args.reverse;
+    let (all_events, _) = fetch_with_spinner(config, false)?;
+    if teaching_days {
+        target_date = teaching_day(today, offset, week_length, |date| !day_events(&all_events.events, date).is_empty());
+    }
+    options.hide_finished = hide_finished && target_date == status_now().date_naive();
+    if view.fingerprint != view_fingerprint(target_date, &options) {
+        return Err("The last numbered view was of a different day, or had other filters or ordering; please re-run bstt first, with the same flags and --numbered.".into());
+    }
+    Ok((all_events.events, view))
+}
+
+fn show_event(config: Config, number: usize, args: NumberedViewArgs) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let display = config.display.clone();
+    let (events, view) = numbered_view(config, args)?;
+    display_event_details(last_view_event(&events, &view, number)?, &display);
+    Ok(())
+}
+
+const EXAMPLES: &[(&str, &str)] = &[
+    ("bstt +1", "Tomorrow's timetable"),
+    ("bstt week --compact", "This week, one line per day"),
+    ("bstt week +7 --type lecture", "Next week's lectures"),
+    ("bstt mini", "A status line for Polybar (`exec = bstt mini`, `interval = 60`)"),
+    ("bstt waybar", "JSON for a Waybar custom module, with the rest of the day as its tooltip"),
+    ("bstt export --week --format json | jq '.events[].title'", "This week's events, piped as JSON"),
+    ("bstt --numbered && bstt show 2", "Every detail of the second event today"),
+    ("bstt brief", "Today in a few lines: the first event, the longest gap, anything to watch for and when you're done"),
+    ("0 18 * * 0 bstt report --week | mail -s \"$(bstt report --week --subject)\" me@example.com", "A crontab line mailing the coming week every Sunday evening"),
+    ("* * * * * bstt notify --before 10", "A crontab line for a desktop notification ten minutes before each event"),
+    ("bstt snooze chess", "Stop notifications about the next event with \"chess\" in its title"),
+    ("bstt serve --port 8080", "Today's events as JSON on http://127.0.0.1:8080/"),
+    ("bstt serve --daemonize && bstt webcal-url", "Run the server in the background and print a URL to subscribe to in a calendar app"),
+    ("bstt --timezone America/New_York", "Today's timetable in New York time"),
+    ("bstt import --ical ~/Downloads/society.ics", "Show a society's calendar alongside the timetable"),
+];
+
+fn print_examples() {
+    for (command, explanation) in EXAMPLES {
+        println!("  {}\n      {}\n", command.bold(), explanation);
+    }
+}
+
+/// Saves `cookie` (or one read from stdin) into the config file, keeping everything else in it.
+fn save_cookie(cookie: Option<String>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cookie = match cookie {
+        Some(cookie) => cookie,
+        None => {
+            let mut cookie = String::new();
+            std::io::stdin().read_line(&mut cookie)?;
+            cookie
+        }
+    };
+    let cookie = cookie.trim();
+    if cookie.is_empty() {
+        return Err("No cookie given.".into());
+    }
+    let config_path = Path::new(CONFIG_DIR).join(CONFIG_FILE);
+    // An existing file that can't be read is an error, rather than something to write over.
+    let mut table: toml::Table = if config_path.exists() { toml::from_str(&read_config_at(&config_path)?)? } else { toml::Table::new() };
+    let api = table.entry("api").or_insert_with(|| toml::Table::new().into()).as_table_mut().ok_or("[api] in the config file is not a table.")?;
+    api.insert("cookie".to_string(), cookie.into());
+    let contents = toml::to_string(&table)?;
+    fs::create_dir_all(CONFIG_DIR).and_then(|_| fs::write(&config_path, contents))
+        .map_err(|e| format!("Failed to write '{}': {}. Try running with sudo.", config_path.display(), e))?;
+    eprintln!("Saved the cookie to '{}'.", config_path.display());
+    Ok(())
+}
+
+fn config_command(config: Config, origins: &BTreeMap<String, Origin>, config_path: &Path, action: ConfigAction) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match action {
+        ConfigAction::Path => println!("{}", config_path.display()),
+        ConfigAction::Show { origins: with_origins } => {
+            let mut config = config;
+            config.api.cookie = "(hidden)".to_string();
+            if with_origins {
+                print!("{}", config_with_origins(&config, origins)?);
+            } else {
+                print!("{}", toml::to_string(&config)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The day `day_offset` days from today, stretching the fetch window to reach it (or its whole
+/// week, with `week`).
+fn target_date(config: &mut Config, day_offset: &str, week: bool) -> Result<NaiveDate, Box<dyn Error + Send + Sync>> {
+    let offset: i64 = day_offset.parse().map_err(|_| "Invalid day offset.")?;
+    let target_date = display_today() + Duration::days(offset);
+    if week {
+        extend_window_to(&mut config.api, week_start(target_date), week_start(target_date) + Duration::days(6));
+    } else {
+        extend_window_to(&mut config.api, target_date, target_date);
+    }
+    Ok(target_date)
+}
+
+/// Stretches the fetch window to reach `offset` teaching days from today. Each week counted
+/// through can add its days off, and the last may end on some.
+fn extend_for_teaching_days(api: &mut ApiConfig, offset: i64, week_length: u32) {
+    let today = display_today();
+    let week_length = i64::from(week_length.clamp(1, 7));
+    let reach = offset.abs() + (7 - week_length) * (offset.abs() / week_length + 1);
+    let furthest = today + Duration::days(reach * offset.signum());
+    extend_window_to(api, furthest.min(today), furthest.max(today));
+}
+
+/// The day `offset` teaching days from `from`, counting back for a negative offset. A teaching
+/// day is one of the first `week_length` days of the week or any day with something on, so an
+/// empty weekend is skipped but a Saturday field trip still counts.
+fn teaching_day(from: NaiveDate, offset: i64, week_length: u32, has_events: impl Fn(NaiveDate) -> bool) -> NaiveDate {
+    let step = Duration::days(offset.signum());
+    let mut date = from;
+    for _ in 0..offset.abs() {
+        date += step;
+        while date.weekday().num_days_from_monday() >= week_length.max(1) && !has_events(date) {
+            date += step;
+        }
+    }
+    date
+}
+
+fn view_options(config: &Config, filters: FilterArgs, show_links: bool, weekday_labels: Option<WeekdayLabel>) -> ViewOptions {
+    ViewOptions {
+        numbered: false,
+        show_links,
+        hyperlinks: match config.display.hyperlinks {
+            HyperlinkMode::Auto => std::io::stdout().is_terminal() && terminal_supports_hyperlinks(),
+            HyperlinkMode::Always => true,
+            HyperlinkMode::Never => false,
+        },
+        display: config.display.clone(),
+        calendar: config.academic_calendar.clone(),
+        search: filters.search,
+        types: filters.types,
+        exclude: filters.exclude,
+        min_duration: filters.min_duration,
+        hide_finished: false,
+        weekday_labels,
+        merge_adjacent: false,
+        show_level: false,
+        reverse: false,
+        width: None,
+    }
+}
+
+/// Prints the output, through `$PAGER` (`less -R` by default) when it's for a terminal too short
+/// to show it all, so that its top doesn't scroll out of sight. A pager that can't be started
+/// is noted under --verbose and the output printed as usual.
+fn page_output(output: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let rows = crossterm::terminal::size().map(|(_, rows)| usize::from(rows)).ok().filter(|_| std::io::stdout().is_terminal());
+    if rows.is_none_or(|rows| output.lines().count() < rows) {
+        print!("{}", output);
+        return Ok(());
+    }
+    let pager = env::var("PAGER").ok().filter(|pager| !pager.trim().is_empty()).unwrap_or_else(|| "less -R".to_string());
+    // $PAGER may carry arguments of its own, as the default does.
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    match std::process::Command::new(program).args(words).stdin(std::process::Stdio::piped()).spawn() {
+        Ok(mut child) => {
+            if let Some(stdin) = child.stdin.take() {
+                // Quitting the pager before the end closes the pipe, which isn't worth reporting.
+                let mut writer = std::io::BufWriter::new(stdin);
+                let _ = writer.write_all(output.as_bytes()).and_then(|_| writer.flush());
+            }
+            child.wait().map_err(|e| format!("The pager '{}' failed: {}", pager, e))?;
+        }
+        Err(e) => {
+            log_verbose(format!("Could not start the pager '{}': {}", pager, e));
+            print!("{}", output);
+        }
+    }
+    Ok(())
+}
+
+fn print_output(output: &str, clipboard: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    page_output(output)?;
+    if clipboard {
+        copy_to_clipboard(&strip_ansi(output))?;
+        eprintln!("Copied to clipboard.");
+    }
+    Ok(())
+}
+
+fn day_view(mut config: Config, view: ViewArgs, qr: bool, qr_event: Option<usize>, numbered: bool, pin: Vec<String>, rooms: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut target_date = target_date(&mut config, &view.day_offset, false)?;
+    let today = display_today();
+    let offset = target_date.signed_duration_since(today).num_days();
+    let teaching_days = config.display.teaching_days && offset != 0;
+    let week_length = config.display.week_length;
+    if teaching_days {
+        extend_for_teaching_days(&mut config.api, offset, week_length);
+    }
+    config.display.pinned.extend(pin);
+    let labels = view.weekday_labels.or(config.display.day_weekday_labels);
+    let mut options = view_options(&config, view.filters, view.show_links, labels);
+    options.merge_adjacent = view.merge_adjacent;
+    options.show_level = view.show_level;
+    options.reverse = view.reverse;
+    options.width = view.width;
+    options.numbered = numbered || (config.display.numbered && std::io::stdout().is_terminal());
+    let now = status_now();
+    // Other days have nothing "finished" to hide.
+    options.hide_finished = config.display.hide_finished && target_date == now.date_naive();
+    let (all_events, report) = fetch_with_spinner(config, false)?;
+    let mut skipped_note = String::new();
+    if teaching_days {
+        target_date = teaching_day(today, offset, week_length, |date| !day_events(&all_events.events, date).is_empty());
+        let counted = today + Duration::days(offset);
+        let skipped = target_date.signed_duration_since(counted).num_days().abs();
+        if skipped > 0 {
+            let plural = if offset.abs() == 1 { "" } else { "s" };
+            let message = format!("{:+} teaching day{} is {}, skipping {} day{} with nothing on.", offset, plural, target_date.format("%A %d %B"), skipped, if skipped == 1 { "" } else { "s" });
+            writeln!(skipped_note, " {}", message.dimmed()).unwrap();
+        }
+    }
+    let mut daily_events = day_events(&all_events.events, target_date);
+    let counts = apply_filters(&mut daily_events, &options);
+    if options.hide_finished {
+        let before = daily_events.len();
+        daily_events.retain(|event| !is_over(event, now));
+        log_verbose(format!("Hid {} finished events", before - daily_events.len()));
+    }
+    let meta = output_meta(all_events.events.len(), &report, counts);
+    let files = write_view_files(view.ics_file.as_deref(), view.json_file.as_deref(), &daily_events, &options.display, &meta);
+
+    if let Some(format) = view.format.structured() {
+        return print_output(&render_structured(format, &daily_events, &options.display, Some(&meta))?, view.clipboard).and(files);
+    }
+    if rooms {
+        return print_output(&render_rooms(&daily_events, &options.display), view.clipboard).and(files);
+    }
+    pin_events(&mut daily_events, &options);
+    if options.numbered {
+        if let Err(e) = save_last_view(view_fingerprint(target_date, &options), &daily_events) {
+            log_warning(format!("Could not save the numbered view: {}", e));
+        }
+    }
+    let context = ViewContext::new(target_date);
+    let qr_code = match qr_event {
+        Some(number) => Some(daily_events.get(number.wrapping_sub(1)).ok_or_else(|| format!("There is no event #{} on this day.", number))?),
+        None if qr => daily_events.iter().find(|event| parse_timestamp(&event.start).is_some_and(|start| start > context.now)),
+        None => None,
+    };
+    let qr_code = qr_code.map(render_qr).transpose()?;
+    if qr && qr_code.is_none() {
+        log_warning("There is no upcoming event on this day to encode.");
+    }
+    let mut output = skipped_note + &render_timetable(daily_events, &context, &options, &report);
+    if let Some(qr_code) = qr_code {
+        writeln!(output, "\n{}", qr_code).unwrap();
+    }
+    print_output(&output, view.clipboard).and(files)
+}
+
+/// A field `day --list-types` and its kin count the values of.
+#[derive(Clone, Copy)]
+enum ListField {
+    Types,
+    Locations,
+    Lecturers,
+}
+
+/// Prints the distinct values of `field` across the fetched window (after any filters) with how
+/// many events have each, most common first, so that filters can be given the API's own words.
+fn list_field(config: Config, filters: FilterArgs, field: ListField) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let options = view_options(&config, filters, false, None);
+    let (all_events, _) = fetch_with_spinner(config, false)?;
+    let mut events = all_events.events;
+    apply_filters(&mut events, &options);
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for event in &events {
+        let values: Vec<&str> = match field {
+            ListField::Types => vec![&event.event_type],
+            ListField::Locations => vec![&event.location],
+            // Several lecturers come as one comma-separated string.
+            ListField::Lecturers => event.teacher_name.iter().flat_map(|names| names.split(',')).map(str::trim).collect(),
+        };
+        for value in values.into_iter().filter(|value| !value.is_empty()) {
+            *counts.entry(value).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    // Stable, so that equally common values stay in alphabetical order.
+    counts.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+    let Some(&(_, most)) = counts.first() else {
+        eprintln!("None of the fetched events have one.");
+        return Ok(());
+    };
+    let width = most.to_string().len();
+    for (value, count) in counts {
+        println!("{:>width$}  {}", count, value);
+    }
+    Ok(())
+}
+
+fn week_view(mut config: Config, view: ViewArgs, compact: bool, skip_weekends: bool, weeks: usize) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let target_date = target_date(&mut config, &view.day_offset, true)?;
+    let monday = week_start(target_date);
+    extend_window_to(&mut config.api, monday, monday + Duration::weeks(weeks as i64) - Duration::days(1));
+    config.display.skip_weekends |= skip_weekends;
+    let labels = view.weekday_labels.or(config.display.week_weekday_labels);
+    let mut options = view_options(&config, view.filters, view.show_links, labels);
+    options.merge_adjacent = view.merge_adjacent;
+    options.reverse = view.reverse;
+    options.width = view.width;
+    let (all_events, report) = fetch_with_spinner(config, false)?;
+    let (weeks, counts) = weeks_days(&all_events.events, monday, weeks, &options);
+    let events: Vec<Event> = weeks.iter().flatten().flat_map(|(_, events)| events.iter().cloned()).collect();
+    let meta = output_meta(all_events.events.len(), &report, counts);
+    let files = write_view_files(view.ics_file.as_deref(), view.json_file.as_deref(), &events, &options.display, &meta);
+    let output = match view.format.structured() {
+        Some(ExportFormat::Markdown) => render_weeks_markdown(&weeks, &options),
+        Some(format) => render_structured(format, &events, &options.display, Some(&meta))?,
+        None if weeks.len() > 1 => render_weeks(&weeks, &options) + &report_footer(&report),
+        None if compact => render_compact_week(&weeks[0], &options) + &report_footer(&report),
+        None => render_week(&weeks[0], &options, &report),
+    };
+    print_output(&output, view.clipboard).and(files)
+}
+
+/// Writes the view's events to each `--ics-file` and `--json-file` given, so that one fetch
+/// serves them all. Each is written even if another can't be.
+fn write_view_files(ics_file: Option<&Path>, json_file: Option<&Path>, events: &[Event], display: &DisplayConfig, meta: &serde_json::Value) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut failed = 0;
+    for (format, path) in [(ExportFormat::Ics, ics_file), (ExportFormat::Json, json_file)] {
+        let Some(path) = path else { continue };
+        let path = expand_home(path);
+        let written = render_structured(format, events, display, Some(meta))
+            .and_then(|contents| fs::write(&path, contents).map_err(Into::into));
+        match written {
+            Ok(()) => log_verbose(format!("Wrote {} events to '{}'", events.len(), path.display())),
+            Err(e) => {
+                log_warning(format!("Could not write '{}': {}", path.display(), e));
+                failed += 1;
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        1 => Err("An output file couldn't be written.".into()),
+        _ => Err(format!("{} output files couldn't be written.", failed).into()),
+    }
+}
+
+fn gantt_view(mut config: Config, day_offset: String, filters: FilterArgs, width: usize, start: Option<u32>, end: Option<u32>) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let target_date = target_date(&mut config, &day_offset, false)?;
+    let hours = (start.unwrap_or(config.display.gantt_start_hour), end.unwrap_or(config.display.gantt_end_hour));
+    if hours.0 >= hours.1 || hours.1 > 24 {
+        return Err(format!("The timeline can't run from {}:00 to {}:00; the start must be before the end, which can be 24 at the latest.", hours.0, hours.1).into());
+    }
+    if width == 0 {
+        return Err("--width must be at least 1.".into());
+    }
+    let options = view_options(&config, filters, false, None);
+    let (all_events, _) = fetch_with_spinner(config, false)?;
+    let mut daily_events = day_events(&all_events.events, target_date);
+    apply_filters(&mut daily_events, &options);
+    print!("{}", render_gantt(&daily_events, target_date, hours, width, &options.display));
+    Ok(())
+}
+
+/// Exports the day, or with `weeks` that many weeks from the one containing it.
+fn export(mut config: Config, day_offset: String, filters: FilterArgs, format: ExportFormat, weeks: usize, output: Option<PathBuf>, open: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let target_date = target_date(&mut config, &day_offset, weeks > 0)?;
+    let monday = week_start(target_date);
+    if weeks > 1 {
+        extend_window_to(&mut config.api, monday, monday + Duration::weeks(weeks as i64) - Duration::days(1));
+    }
+    let options = view_options(&config, filters, false, None);
+    let (all_events, report) = fetch_with_spinner(config, false)?;
+    let mut by_week = None;
+    let (events, counts): (Vec<Event>, FilterCounts) = if DATE_RANGE.get().is_some() {
+        // Already cut down to the range, so a semester can be exported at once.
+        let mut events = all_events.events.clone();
+        events.sort_by(|a, b| a.start.cmp(&b.start).then_with(|| a.title.cmp(&b.title)));
+        let counts = apply_filters(&mut events, &options);
+        (events, counts)
+    } else if weeks > 0 {
+        let (days, counts) = weeks_days(&all_events.events, monday, weeks, &options);
+        let events = days.iter().flatten().flat_map(|(_, events)| events.iter().cloned()).collect();
+        by_week = Some(days);
+        (events, counts)
+    } else {
+        let mut daily_events = day_events(&all_events.events, target_date);
+        let counts = apply_filters(&mut daily_events, &options);
+        (daily_events, counts)
+    };
+    let meta = output_meta(all_events.events.len(), &report, counts);
+    let rendered = match (format, by_week) {
+        (ExportFormat::Markdown, Some(weeks)) => render_weeks_markdown(&weeks, &options),
+        _ => render_structured(format, &events, &options.display, Some(&meta))?,
+    };
+    let Some(output) = output else {
+        print!("{}", rendered);
+        return Ok(());
+    };
+    let output = expand_home(&output);
+    fs::write(&output, rendered).map_err(|e| format!("Failed to write '{}': {}.", output.display(), e))?;
+    eprintln!("Wrote {} events to '{}'.", events.len(), output.display());
+    if open {
+        open::that(&output).map_err(|e| format!("Could not open '{}': {}", output.display(), e))?;
+    }
+    Ok(())
+}
+
+/// `bstt mini` or `bstt waybar`, escaped for `output`. Errors become status text so
+/// the bar shows something rather than nothing.
+fn status_bar(config: Config, output: BarOutput) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let display = config.display.clone();
+    let labels = config.mini.clone();
+    let calendar = config.academic_calendar.clone();
+    let (all_events, report) = match fetch_with_spinner(config, true) {
+        Ok(events) => events,
+        Err(e) if matches!(output, BarOutput::Waybar { .. }) => {
+            println!("{}", serde_json::json!({ "text": output.escape(&labels.error), "tooltip": output.escape(&e.to_string()), "class": "error" }));
+            return Ok(());
+        }
+        Err(_) => { print!("{}", output.escape(&labels.error)); return Ok(()); }
+    };
+    match output {
+        BarOutput::Waybar { markup } => display_waybar_timetable(all_events, markup, &report, &display, &labels),
+        _ => print!("{}", output.escape(&mini_status(all_events, &report, &display, &labels, &calendar))),
+    }
+    Ok(())
+}
+
+/// `bstt mini --watch`: recomputes the line every `interval` and prints it, or replaces `file`
+/// with it. The file is written atomically, so a bar reading it never sees half a line.
+fn watch_mini(config: Config, output: BarOutput, file: Option<&Path>, interval: std::time::Duration) -> Result<(), Box<dyn Error + Send + Sync>> {
+    loop {
+        let (display, labels, calendar) = (config.display.clone(), config.mini.clone(), config.academic_calendar.clone());
+        let line = match fetch_with_spinner(config.clone(), true) {
+            Ok((events, report)) => mini_status(events, &report, &display, &labels, &calendar),
+            Err(e) => {
+                log_verbose(format!("Fetch failed: {}", e));
+                labels.error.clone()
+            }
+        };
+        let line = format!("{}\n", output.escape(&line));
+        match file {
+            Some(path) => write_atomically(path, line.as_bytes())
+                .map_err(|e| format!("Could not write the status line to '{}': {}", path.display(), e))?,
+            None => {
+                print!("{}", line);
+                std::io::stdout().flush()?;
+            }
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let cli = Cli::parse_from(legacy_args(env::args_os().collect()));
+    VERBOSE.store(cli.verbose, Ordering::Relaxed);
+    if !std::io::stdout().is_terminal() {
+        colored::control::set_override(false);
+    }
+    match cli.command {
+        Command::Auth { cookie } => return save_cookie(cookie),
+        Command::Examples => { print_examples(); return Ok(()); }
+        _ => {}
+    }
+    let ResolvedConfig { mut config, origins, path: config_path } = load_or_create_config(&cli)?;
+    if let Some(path) = config.log.file.clone() {
+        let _ = LOG_FILE.set(path);
+    }
+    let _ = THEME.set(config.display.theme);
+    let locale = |name| env::var(name).ok();
+    let _ = ASCII.set(use_ascii(
+        config.display.ascii,
+        locale("LC_ALL").as_deref(),
+        locale("LC_CTYPE").as_deref(),
+        locale("LANG").as_deref(),
+    ));
+    if let Some(unit) = config.display.focus.clone().filter(|unit| !unit.trim().is_empty()) {
+        let _ = FOCUS.set(unit);
+    }
+    let _ = STALE_HOURS.set((config.cache.stale_warning_hours, config.cache.stale_alert_hours));
+    let _ = STRIP_GROUPS.set(config.display.strip_groups);
+    let lowered = |patterns: &[String]| patterns.iter().map(|pattern| pattern.trim().to_lowercase()).filter(|pattern| !pattern.is_empty()).collect();
+    let _ = ONLINE_PATTERNS.set((lowered(&config.display.online_locations), lowered(&config.display.online_titles)));
+    if let Some(base_url) = &config.api.base_url {
+        let _ = CAMPUSM_BASE_URL.set(base_url.trim_end_matches('/').to_string());
+    }
+    if cli.utc {
+        let _ = DISPLAY_ZONE.set(DisplayZone::Override(Tz::UTC));
+    } else if let Some(timezone) = &cli.timezone {
+        let zone = timezone.parse().map_err(|_| format!("Unknown timezone '{}'; use an IANA name such as America/New_York.", timezone))?;
+        let _ = DISPLAY_ZONE.set(DisplayZone::Override(zone));
+    } else if let Some(timezone) = &config.display.timezone {
+        let _ = DISPLAY_ZONE.set(DisplayZone::Configured(timezone.parse()?));
+    }
+    if let Some(at) = &cli.at {
+        let time = NaiveTime::parse_from_str(at, "%H:%M").map_err(|_| format!("Invalid --at time '{}'; use HH:MM, e.g. 15:00.", at))?;
+        let _ = AT_TIME.set(time);
+    }
+    if cli.since.is_some() || cli.until.is_some() {
+        if let Some((since, until)) = cli.since.zip(cli.until).filter(|(since, until)| since > until) {
+            return Err(format!("--since {} is after --until {}.", since, until).into());
+        }
+        let today = display_today();
+        extend_window_to(&mut config.api, cli.since.unwrap_or(today), cli.until.unwrap_or(today));
+        let _ = DATE_RANGE.set((cli.since, cli.until));
+    }
+    let profile = active_profile(&cli);
+    match cli.command {
+        Command::Day { view, list_types, list_locations, list_lecturers, .. } if list_types || list_locations || list_lecturers => {
+            let field = if list_types { ListField::Types } else if list_locations { ListField::Locations } else { ListField::Lecturers };
+            list_field(config, view.filters, field)
+        }
+        Command::Day { view, qr, qr_event, numbered, pin, rooms, no_prev, prev, color_by, teaching_day, calendar_day, .. } => {
+            config.display.hide_finished = (config.display.hide_finished || no_prev) && !prev;
+            config.display.teaching_days = (config.display.teaching_days || teaching_day) && !calendar_day;
+            config.display.color_by = color_by.or(config.display.color_by);
+            day_view(config, view, qr, qr_event, numbered, pin, rooms)
+        }
+        Command::Week { view, compact, skip_weekends, weeks } => week_view(config, view, compact, skip_weekends, weeks as usize),
+        Command::Gantt { day_offset, filters, width, start, end } => gantt_view(config, day_offset, filters, width, start, end),
+        Command::Export { day_offset, filters, format, week, weeks, output, open } => {
+            let weeks = weeks.map_or(usize::from(week), |weeks| weeks as usize);
+            export(config, day_offset, filters, format, weeks, output, open)
+        }
+        Command::Mini { polybar, watch: true, watch_file, interval } => {
+            let output = if polybar { BarOutput::Polybar } else { BarOutput::Plain };
+            watch_mini(config, output, watch_file.as_deref(), std::time::Duration::from_secs(interval))
+        }
+        Command::Mini { polybar, .. } => status_bar(config, if polybar { BarOutput::Polybar } else { BarOutput::Plain }),
+        Command::Waybar { no_markup } => status_bar(config, BarOutput::Waybar { markup: !no_markup }),
+        Command::Serve { address, daemonize: true, .. } => daemonize(&address),
+        Command::Serve { address, cache_interval, .. } => serve(config, &address, Duration::seconds(cache_interval)),
+        Command::BankHolidays => {
+            list_bank_holidays(&config);
+            Ok(())
+        }
+        Command::UpdateHolidays => update_holidays(),
+        Command::WebcalUrl { address } => {
+            println!("webcal://{}:{}/calendar.ics", serve_host(&address.bind), address.port);
+            Ok(())
+        }
+        Command::Show { number, view } => show_event(config, number, view),
+        Command::Brief { day_offset, date, filters, json } => brief_command(config, day_offset, date, filters, json),
+        Command::Report { week: _, day_offset, format, subject } => report(config, day_offset, format, subject),
+        Command::Shared { cookies, date, min_duration, day_start, day_end, format } => shared(config, cookies, date, min_duration, (&day_start, &day_end), format),
+        Command::Notify { before, filters } => notify(config, before, filters),
+        Command::Alarm { lead, set, filters } => alarm(config, lead, set, filters),
+        Command::Focus { unit, clear } => focus_command(&config, profile.as_deref(), unit, clear),
+        Command::Compress { text, location, explain, .. } => compress_command(&config.display, text, location, explain),
+        Command::Changes { limit } => changes_command(&config.display, limit),
+        Command::ApiStats => api_stats(&config),
+        Command::Snooze { event, view } => snooze(config, &event, view),
+        Command::Archive { year, out, force } => archive(config, &year, &out, force),
+        Command::Import { ical, .. } => import_command(ical),
+        Command::Cache { action } => cache_command(config, action),
+        Command::Config { action } => config_command(config, &origins, &config_path, action),
+        Command::Auth { .. } | Command::Examples => unreachable!("handled before the config is loaded"),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        log_line(&format!("{} {}", paint("Error:", Role::Problem).bold(), e));
         std::process::exit(1);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, day).unwrap()
+    }
+
+    /// Shows times in Europe/London. The zone can only be set once, so every test that needs
+    /// it goes through here.
+    fn display_in_london() {
+        let _ = DISPLAY_ZONE.set(DisplayZone::Override(chrono_tz::Europe::London));
+        assert!(matches!(DISPLAY_ZONE.get(), Some(DisplayZone::Override(chrono_tz::Europe::London))));
+    }
+
+    fn lecture(start: &str, end: &str) -> Event {
+        Event {
+            title: "COMS10016: Imperative Programming".to_string(),
+            event_type: "Lecture".to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            location: "Queens Building 1.40".to_string(),
+            teacher_name: Some("Dr Smith".to_string()),
+            booking_id: None,
+            level: None,
+            issue: None,
+        }
+    }
+
+    #[test]
+    fn only_todays_view_is_decorated_with_the_current_time() {
+        display_in_london();
+        // Monday 19 October 2026, half way through the lecture.
+        let now = DateTime::parse_from_rfc3339("2026-10-19T11:00:00+01:00").unwrap();
+        let report = FetchReport {
+            window_start: Utc.from_utc_datetime(&date(1).and_hms_opt(0, 0, 0).unwrap()),
+            window_end: Utc.from_utc_datetime(&date(31).and_hms_opt(0, 0, 0).unwrap()),
+            ..FetchReport::default()
+        };
+        let render = |target_date: NaiveDate, event: Event| {
+            let context = ViewContext { target_date, now, is_today: target_date == now.date_naive() };
+            render_timetable(vec![event], &context, &ViewOptions::default(), &report)
+        };
+        // The same lecture at the same local time, today and a week later (after the clocks change).
+        let today = render(date(19), lecture("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00"));
+        let next_week = render(date(26), lecture("2026-10-26T10:00:00+00:00", "2026-10-26T12:00:00+00:00"));
+        for out in [&today, &next_week] {
+            assert!(out.contains("10:00 - 12:00"), "{}", out);
+            assert!(out.contains("Imperative Programming"), "{}", out);
+        }
+        assert!(today.contains("(Today)"), "{}", today);
+        assert!(today.contains("▶ 10:00"), "{}", today);
+        assert!(!next_week.contains("Today"), "{}", next_week);
+        assert!(!next_week.contains('▶'), "{}", next_week);
+    }
+
+    #[test]
+    fn online_events_and_their_joining_urls() {
+        let cases = [
+            // (location, title, online, joining URL)
+            ("Queens Building 1.40", "Imperative Programming", false, None),
+            ("Zoology Building G.10", "Imperative Programming", false, None),
+            ("MS Teams (https://teams.microsoft.com/l/meetup-join/abc)", "Imperative Programming", true, Some("https://teams.microsoft.com/l/meetup-join/abc")),
+            ("Online: https://bristol.zoom.us/j/123.", "Imperative Programming", true, Some("https://bristol.zoom.us/j/123")),
+            ("Online", "Office hours, join at https://bristol.zoom.us/j/456", true, Some("https://bristol.zoom.us/j/456")),
+            ("Queens Building 1.40", "Imperative Programming (online)", true, None),
+            ("Queens Building 1.40", "Slides at https://example.ac.uk/slides", false, Some("https://example.ac.uk/slides")),
+        ];
+        for (location, title, online, url) in cases {
+            let event = Event { location: location.to_string(), title: title.to_string(), ..lecture("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00") };
+            assert_eq!(is_online(&event), online, "{} / {}", location, title);
+            assert_eq!(joining_url(&event), url, "{} / {}", location, title);
+        }
+    }
+
+    #[test]
+    fn decomposed_accents_are_normalised_to_composed() {
+        let (composed, decomposed) = ("Dr M\u{fc}ller", "Dr Mu\u{308}ller");
+        assert_ne!(composed, decomposed);
+        // The last lists both forms, which are the same lecturer once normalised.
+        let both = format!("{}, {}", composed, decomposed);
+        let events = [composed, decomposed, &both].map(|name| serde_json::json!({
+            "desc1": format!("Seminar with {}", name), "desc2": "Seminar", "start": "2026-10-19T10:00:00+01:00",
+            "end": "2026-10-19T12:00:00+01:00", "locAdd1": "Fry Building: G.07", "teacherName": name,
+        }));
+        let fixture = serde_json::json!({ "events": events });
+        // Composed as they're read, so even --raw, which skips normalise_event_text, gets it.
+        let (mut events, _) = Bristol { base_url: BRISTOL_BASE_URL.to_string() }.parse_events(&fixture.to_string()).unwrap();
+        for event in &events[..2] {
+            assert_eq!(event.title, format!("Seminar with {}", composed));
+            assert_eq!(event.teacher_name.as_deref(), Some(composed));
+        }
+        assert_eq!(event_id(&events[0]), event_id(&events[1]));
+        normalise_event_text(&mut events);
+        for event in &events {
+            assert_eq!(event.teacher_name.as_deref(), Some(composed));
+        }
+
+        // Imported calendars and events read back from the cache are composed too.
+        let ics = format!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Seminar with {}\r\nDTSTART:20261019T090000Z\r\nLOCATION:Cafe\u{301}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n", decomposed);
+        let calendar = ical::IcalParser::new(ics.as_bytes()).next().unwrap().unwrap();
+        let imported = event_from_vevent(&calendar.events[0]).unwrap();
+        assert_eq!(imported.title, format!("Seminar with {}", composed));
+        assert_eq!(imported.location, "Caf\u{e9}");
+        let cached = CachedEvent::from(&Event { teacher_name: Some(decomposed.to_string()), ..imported });
+        assert_eq!(Event::from(cached).teacher_name.as_deref(), Some(composed));
+    }
+
+    #[test]
+    fn view_fingerprint_covers_everything_that_orders_rows() {
+        let plain = view_fingerprint(date(19), &ViewOptions::default());
+        let variants: [fn(&mut ViewOptions); 8] = [
+            |options| options.types = vec!["Lecture".to_string()],
+            |options| options.search = Some("physics".to_string()),
+            |options| options.exclude = vec!["drop-in".to_string()],
+            |options| options.min_duration = 30,
+            |options| options.display.pinned = vec!["COMS".to_string()],
+            |options| options.merge_adjacent = true,
+            |options| options.reverse = true,
+            |options| options.hide_finished = true,
+        ];
+        for (i, change) in variants.iter().enumerate() {
+            let mut options = ViewOptions::default();
+            change(&mut options);
+            assert_ne!(view_fingerprint(date(19), &options), plain, "variant {}", i);
+        }
+        assert_ne!(view_fingerprint(date(20), &ViewOptions::default()), plain);
+        assert_eq!(view_fingerprint(date(19), &ViewOptions::default()), plain);
+    }
+
+    /// The foreground colours set by `text`'s escape codes, as "rgb(r,g,b)", "256(n)" or "ansi(n)".
+    fn emitted_colours(text: &str) -> Vec<String> {
+        let mut colours = Vec::new();
+        for sequence in text.split("\x1b[").skip(1).filter_map(|rest| rest.split_once('m').map(|(params, _)| params)) {
+            let params: Vec<u32> = sequence.split(';').filter_map(|param| param.parse().ok()).collect();
+            let mut params = params.iter();
+            while let Some(param) = params.next() {
+                match param {
+                    38 => match params.next() {
+                        Some(5) => colours.push(format!("256({})", params.next().unwrap())),
+                        Some(2) => {
+                            let rgb: Vec<_> = params.by_ref().take(3).map(ToString::to_string).collect();
+                            colours.push(format!("rgb({})", rgb.join(",")));
+                        }
+                        _ => {}
+                    },
+                    30..=37 | 90..=97 => colours.push(format!("ansi({})", param)),
+                    _ => {}
+                }
+            }
+        }
+        colours
+    }
+
+    #[test]
+    fn colorblind_theme_never_emits_red_or_green() {
+        let _ = THEME.set(Theme::Colorblind);
+        assert!(matches!(THEME.get(), Some(Theme::Colorblind)));
+        colored::control::set_override(true);
+        let now = DateTime::parse_from_rfc3339("2026-10-19T11:00:00+01:00").unwrap();
+        let report = FetchReport {
+            window_start: Utc.from_utc_datetime(&date(1).and_hms_opt(0, 0, 0).unwrap()),
+            window_end: Utc.from_utc_datetime(&date(25).and_hms_opt(0, 0, 0).unwrap()),
+            ..FetchReport::default()
+        };
+        let current = lecture("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00");
+        let flagged = Event { issue: Some(EventIssue::TooLong), ..lecture("2026-10-19T13:00:00+01:00", "2026-10-20T13:00:00+01:00") };
+        let render = |target_date: NaiveDate, events: Vec<Event>| {
+            let context = ViewContext { target_date, now, is_today: target_date == now.date_naive() };
+            render_timetable(events, &context, &ViewOptions::default(), &report)
+        };
+        // A day with a current and a flagged event, a free day, and one outside the fetched window.
+        let output = [render(date(19), vec![current.clone(), flagged]), render(date(20), vec![]), render(date(28), vec![])].concat();
+        let colours = emitted_colours(&output);
+        // Tables always get the 24-bit palette; other text gets it or its basic fallback,
+        // depending on COLORTERM.
+        for role in [Role::Problem, Role::Location, Role::Time] {
+            let colored::Color::TrueColor { r, g, b } = Theme::Colorblind.colour(role) else { panic!("{:?} isn't 24-bit", role) };
+            assert!(colours.contains(&format!("rgb({},{},{})", r, g, b)), "{:?} wasn't used: {:?}", role, colours);
+        }
+        let red_or_green = ["ansi(31)", "ansi(32)", "ansi(91)", "ansi(92)", "256(1)", "256(2)", "256(9)", "256(10)"];
+        for colour in &colours {
+            assert!(!red_or_green.contains(&colour.as_str()), "red or green: {}", colour);
+            if let Some(rgb) = colour.strip_prefix("rgb(").and_then(|rgb| rgb.strip_suffix(')')) {
+                let rgb: Vec<u32> = rgb.split(',').map(|channel| channel.parse().unwrap()).collect();
+                let (r, g, b) = (rgb[0], rgb[1], rgb[2]);
+                assert!(!(r >= 180 && g <= 80 && b <= 80), "red: {}", colour);
+                assert!(!(g >= 180 && r <= 80 && b <= 80), "green: {}", colour);
+            }
+        }
+        for role in [Role::Heading, Role::Time, Role::EventType, Role::Location, Role::Lecturer, Role::Warning, Role::Problem, Role::Good] {
+            let basic = Theme::Colorblind.basic_colour(role);
+            let forbidden = [colored::Color::Red, colored::Color::Green, colored::Color::BrightRed, colored::Color::BrightGreen];
+            assert!(!forbidden.contains(&basic), "{:?} falls back to {:?}", role, basic);
+        }
+        let mini = mini_line(TimetableState::Current(&current), &DisplayConfig::default(), &MiniConfig::default());
+        assert!(emitted_colours(&mini).is_empty(), "{:?}", mini);
+    }
+
+    #[test]
+    fn relative_day_labels_across_week_boundaries() {
+        // From Wednesday 21 October 2026, whose week is Monday 19 to Sunday 25, or Sunday 18 to
+        // Saturday 24 when weeks start on a Sunday.
+        let cases = [
+            // (offset, weeks from Monday, weeks from Sunday)
+            (-14, "14 days ago", "14 days ago"),
+            (-13, "13 days ago", "13 days ago"),
+            (-12, "12 days ago", "12 days ago"),
+            (-11, "11 days ago", "11 days ago"),
+            (-10, "10 days ago", "last Sunday"),
+            (-9, "last Monday", "last Monday"),
+            (-8, "last Tuesday", "last Tuesday"),
+            (-7, "last Wednesday", "last Wednesday"),
+            (-6, "last Thursday", "last Thursday"),
+            (-5, "last Friday", "last Friday"),
+            (-4, "last Saturday", "last Saturday"),
+            (-3, "last Sunday", "this Sunday"),
+            (-2, "this Monday", "this Monday"),
+            (-1, "Yesterday", "Yesterday"),
+            (0, "Today", "Today"),
+            (1, "Tomorrow", "Tomorrow"),
+            (2, "this Friday", "this Friday"),
+            (3, "this Saturday", "this Saturday"),
+            (4, "this Sunday", "next Sunday"),
+            (5, "next Monday", "next Monday"),
+            (6, "next Tuesday", "next Tuesday"),
+            (7, "next Wednesday", "next Wednesday"),
+            (8, "next Thursday", "next Thursday"),
+            (9, "next Friday", "next Friday"),
+            (10, "next Saturday", "next Saturday"),
+            (11, "next Sunday", "in 11 days"),
+            (12, "in 12 days", "in 12 days"),
+            (13, "in 13 days", "in 13 days"),
+            (14, "in 14 days", "in 14 days"),
+        ];
+        assert_eq!(cases.len(), 29);
+        for (offset, monday, sunday) in cases {
+            let target = date(21) + Duration::days(offset);
+            assert_eq!(relative_day_label(target, date(21), Weekday::Mon), monday, "{:+} from Monday", offset);
+            assert_eq!(relative_day_label(target, date(21), Weekday::Sun), sunday, "{:+} from Sunday", offset);
+        }
+    }
+
+    #[test]
+    fn bar_escaping_handles_markup_in_titles() {
+        let nasty = "%{F#ff0000}Maths & Stats Q&A <b>Drop-in</b>";
+        assert_eq!(escape_pango(nasty), "%{F#ff0000}Maths &amp; Stats Q&amp;A &lt;b&gt;Drop-in&lt;/b&gt;");
+        assert_eq!(escape_polybar(nasty), "%%{F#ff0000}Maths & Stats Q&A <b>Drop-in</b>");
+        assert_eq!(escape_pango("&amp;"), "&amp;amp;");
+        assert_eq!(escape_polybar("100%"), "100%%");
+        for output in [BarOutput::Plain, BarOutput::Waybar { markup: false }] {
+            assert_eq!(output.escape(nasty), nasty);
+        }
+        assert_eq!(BarOutput::Polybar.escape(nasty), escape_polybar(nasty));
+        assert_eq!(BarOutput::Waybar { markup: true }.escape(nasty), escape_pango(nasty));
+    }
+
+    #[test]
+    fn bar_escaping_comes_after_compression() {
+        let event = Event { title: "Maths & Stats Q&A <b>%{F#ff0000}".to_string(), ..lecture("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00") };
+        let display = DisplayConfig { density: 1, ..DisplayConfig::default() };
+        let line = mini_line(TimetableState::Current(&event), &display, &MiniConfig::default());
+        // Compression saw the visible " & ", which an escaped "&amp;" would have hidden from it.
+        assert!(line.contains("Maths + Stats Q&A <b>%{F#ff0000}"), "{}", line);
+        let pango = BarOutput::Waybar { markup: true }.escape(&line);
+        assert!(pango.contains("Maths + Stats Q&amp;A &lt;b&gt;%{F#ff0000}"), "{}", pango);
+        assert_eq!(pango.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&"), line);
+        let polybar = BarOutput::Polybar.escape(&line);
+        assert!(polybar.contains("%%{F#ff0000}"), "{}", polybar);
+        assert_eq!(polybar.replace("%%", "%"), line);
+    }
+
+    /// An empty directory of its own for a test to write files in.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("bstt-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn cache_bytes(format: CacheFormat, event_count: usize) -> Vec<u8> {
+        let event = lecture("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00");
+        let cache = EventCache {
+            schema_version: CACHE_SCHEMA_VERSION,
+            fetched_at: 0,
+            window_start: 0,
+            window_end: 0,
+            events: (0..event_count).map(|_| CachedEvent::from(&event)).collect(),
+        };
+        match format {
+            CacheFormat::Binary => [CACHE_MAGIC.as_slice(), &postcard::to_allocvec(&cache).unwrap()].concat(),
+            CacheFormat::Json => serde_json::to_vec_pretty(&cache).unwrap(),
+        }
+    }
+
+    #[test]
+    fn truncated_or_corrupt_caches_are_misses_and_set_aside() {
+        for format in [CacheFormat::Binary, CacheFormat::Json] {
+            let whole = cache_bytes(format, 3);
+            let damaged: [(&str, Vec<u8>); 3] = [
+                ("truncated", whole[..whole.len() / 2].to_vec()),
+                ("empty", Vec::new()),
+                ("garbage", b"BSTT\xff\xff\xff\xff\xff not a cache {".to_vec()),
+            ];
+            for (name, bytes) in damaged {
+                let dir = scratch_dir(&format!("cache-{:?}-{}", format, name));
+                let path = cache_path_in(&dir, format);
+                fs::write(&path, &bytes).unwrap();
+                let error = load_cache_in(&dir).err().unwrap_or_else(|| panic!("{:?} {} cache was read", format, name));
+                assert_eq!(error.to_string(), NO_CACHE, "{:?} {}", format, name);
+                assert!(!path.exists(), "{:?} {} cache left in place", format, name);
+                let aside = path.with_extension(format!("{}.corrupt", path.extension().unwrap().to_string_lossy()));
+                assert_eq!(fs::read(&aside).unwrap(), bytes, "{:?} {}", format, name);
+                // With it aside, the next run sees no cache at all rather than the same problem.
+                assert_eq!(load_cache_in(&dir).err().unwrap().to_string(), NO_CACHE);
+                fs::write(&path, &whole).unwrap();
+                assert_eq!(load_cache_in(&dir).unwrap().0.events.len(), 3, "{:?}", format);
+                let _ = fs::remove_dir_all(&dir);
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_atomic_writes_always_leave_a_whole_cache() {
+        let dir = scratch_dir("concurrent-writes");
+        let path = cache_path_in(&dir, CacheFormat::Json);
+        write_atomically(&path, &cache_bytes(CacheFormat::Json, 1)).unwrap();
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let (dir, done) = (dir.clone(), done.clone());
+            thread::spawn(move || {
+                let mut reads = 0;
+                while !done.load(Ordering::Relaxed) {
+                    if let Err(e) = load_cache_in(&dir) {
+                        panic!("read a partial cache: {}", e);
+                    }
+                    reads += 1;
+                }
+                reads
+            })
+        };
+        let writers: Vec<_> = (0..8).map(|writer| {
+            let path = path.clone();
+            thread::spawn(move || {
+                for round in 0..25 {
+                    // Different sizes, so a mix of two writes can't parse by accident.
+                    write_atomically(&path, &cache_bytes(CacheFormat::Json, 1 + (writer * 7 + round) % 40)).unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        assert!(reader.join().unwrap() > 0);
+        assert!(load_cache_in(&dir).is_ok());
+        let leftovers: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(leftovers, [std::ffi::OsString::from(CACHE_FILE_JSON)]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn timetable_state_changes_at_the_exact_second() {
+        let event = |title: &str, start: &str, end: &str| Event {
+            title: title.to_string(),
+            ..lecture(&format!("2026-10-19T{}:00+01:00", start), &format!("2026-10-19T{}:00+01:00", end))
+        };
+        // A and B are back to back, Z is a zero-length marker and C is the last of the day.
+        let events = [event("A", "09:00", "10:00"), event("B", "10:00", "11:00"), event("Z", "12:00", "12:00"), event("C", "13:00", "14:00")];
+        let cases = [
+            ("08:59:59", "next A"),
+            ("09:00:00", "current A"),
+            ("09:49:59", "current A"),
+            ("09:50:00", "border A B"),
+            ("09:59:59", "border A B"),
+            ("10:00:00", "current B"),
+            ("10:49:59", "current B"),
+            ("10:50:00", "border B Z"),
+            ("10:59:59", "border B Z"),
+            ("11:00:00", "next Z"),
+            ("11:59:59", "next Z"),
+            ("12:00:00", "next Z"),
+            ("12:00:01", "next C"),
+            ("12:59:59", "next C"),
+            ("13:00:00", "current C"),
+            ("13:50:00", "current C"),
+            ("13:59:59", "current C"),
+            ("14:00:00", "blank"),
+        ];
+        for (time, expected) in cases {
+            let now = DateTime::parse_from_rfc3339(&format!("2026-10-19T{}+01:00", time)).unwrap();
+            let state = match classify_timetable_state(&events, now, BORDER_MINUTES, 0) {
+                TimetableState::Current(current) => format!("current {}", current.title),
+                TimetableState::Border { current, next } => format!("border {} {}", current.title, next.title),
+                TimetableState::Next(next) => format!("next {}", next.title),
+                TimetableState::Blank => "blank".to_string(),
+            };
+            assert_eq!(state, expected, "at {}", time);
+        }
+        // On its own, a zero-length event is shown up to its instant and never current.
+        let marker = [event("Z", "12:00", "12:00")];
+        assert_eq!(day_events(&marker, date(19)).len(), 1);
+        for time in ["11:59:59", "12:00:00"] {
+            let now = DateTime::parse_from_rfc3339(&format!("2026-10-19T{}+01:00", time)).unwrap();
+            assert!(matches!(classify_timetable_state(&marker, now, BORDER_MINUTES, 0), TimetableState::Next(_)), "at {}", time);
+            assert!(!is_in_progress(&marker[0], now), "at {}", time);
+        }
+        let after = DateTime::parse_from_rfc3339("2026-10-19T12:00:01+01:00").unwrap();
+        assert!(matches!(classify_timetable_state(&marker, after, BORDER_MINUTES, 0), TimetableState::Blank));
+    }
+
+    #[test]
+    fn locale_decides_ascii_unless_overridden() {
+        type Locale<'a> = Option<&'a str>;
+        let cases: &[(Locale, Locale, Locale, bool)] = &[
+            (None, None, None, true),
+            (None, None, Some("en_GB.UTF-8"), true),
+            (None, None, Some("en_GB.utf8"), true),
+            (None, None, Some("en_GB.Utf-8"), true),
+            (None, None, Some("C.UTF-8"), true),
+            (None, None, Some("C"), false),
+            (None, None, Some("POSIX"), false),
+            (None, None, Some("en_GB.ISO-8859-1"), false),
+            // LC_CTYPE beats LANG.
+            (None, Some("C"), Some("en_GB.UTF-8"), false),
+            (None, Some("en_GB.UTF-8"), Some("C"), true),
+            // LC_ALL beats both.
+            (Some("POSIX"), Some("en_GB.UTF-8"), Some("en_GB.UTF-8"), false),
+            (Some("en_US.utf8"), Some("C"), Some("POSIX"), true),
+            // Set but empty counts as unset.
+            (Some(""), None, Some("C"), false),
+            (Some(""), Some(""), Some(""), true),
+        ];
+        for &(lc_all, lc_ctype, lang, utf8) in cases {
+            assert_eq!(locale_is_utf8(lc_all, lc_ctype, lang), utf8, "LC_ALL={:?} LC_CTYPE={:?} LANG={:?}", lc_all, lc_ctype, lang);
+            assert_eq!(use_ascii(None, lc_all, lc_ctype, lang), !utf8);
+            assert!(use_ascii(Some(true), lc_all, lc_ctype, lang));
+            assert!(!use_ascii(Some(false), lc_all, lc_ctype, lang));
+        }
+
+        // --ascii and --unicode reach `[display] ascii`, and the later one wins.
+        let ascii_setting = |args: &[&str]| {
+            let cli = Cli::try_parse_from(args).unwrap();
+            config::cli_layers(&cli).into_iter().rev().find_map(|(_, layer)| layer["display"].get("ascii")?.as_bool())
+        };
+        assert_eq!(ascii_setting(&["bstt", "--ascii", "day"]), Some(true));
+        assert_eq!(ascii_setting(&["bstt", "--unicode", "day"]), Some(false));
+        assert_eq!(ascii_setting(&["bstt", "--ascii", "day", "--unicode"]), Some(false));
+        assert_eq!(ascii_setting(&["bstt", "day"]), None);
+    }
+
+    #[test]
+    fn location_colours_are_pinned_and_stay_in_the_palette() {
+        // Pinned, so an upgrade doesn't reshuffle everyone's colours.
+        assert_eq!(location_colour_index("Queens Building 1.40", 8), location_colour_index("queens building 1.40", 8));
+        assert_eq!(location_colour_index("Queens Building: 1.40", 8), 6);
+        assert_eq!(location_colour_index("Merchant Venturers Building: 2.11", 8), 3);
+        assert_eq!(location_colour_index("Merchant Venturers Building: 2.11", 6), 5);
+        assert_eq!(location_colour_index("Merchant Venturers Building: 2.11", 3), 2);
+        // Rooms in one building share its colour.
+        assert_eq!(location_colour_index("Queens Building: 1.40", 8), location_colour_index("  Queens Building : G.15", 8));
+
+        let locations = ["Queens Building: 1.40", "Fry Building: G.07", "Merchant Venturers Building: 2.11", "", "Online"];
+        for palette_size in 0..=LOCATION_PALETTE.len() + 4 {
+            for location in locations {
+                let index = location_colour_index(location, palette_size);
+                assert!(index < palette_size.clamp(1, LOCATION_PALETTE.len()), "{:?} got {} of {}", location, index, palette_size);
+            }
+        }
+    }
+
+    #[test]
+    fn hyperlinks_are_framed_as_osc_8_and_take_no_columns() {
+        display_in_london();
+        assert_eq!(hyperlink("text", "https://example.com/a"), "\x1b]8;;https://example.com/a\x1b\\text\x1b]8;;\x1b\\");
+
+        let now = DateTime::parse_from_rfc3339("2026-10-19T09:00:00+01:00").unwrap();
+        let context = ViewContext { target_date: date(19), now, is_today: true };
+        let report = FetchReport {
+            window_start: Utc.from_utc_datetime(&date(1).and_hms_opt(0, 0, 0).unwrap()),
+            window_end: Utc.from_utc_datetime(&date(31).and_hms_opt(0, 0, 0).unwrap()),
+            ..FetchReport::default()
+        };
+        let event = Event { location: "Fry Building: G.07".to_string(), ..lecture("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00") };
+        let render = |hyperlinks: bool| {
+            let mut options = ViewOptions { hyperlinks, ..ViewOptions::default() };
+            options.display.unit_links.insert("COMS10016".to_string(), "https://example.com/coms10016".to_string());
+            render_timetable(vec![event.clone()], &context, &options, &report)
+        };
+        let (linked, plain) = (render(true), render(false));
+
+        let unit = hyperlink("COMS10016: Imperative Programming", "https://example.com/coms10016");
+        let map = hyperlink("Fry Building: G.07", map_url("Fry Building: G.07").unwrap());
+        assert!(linked.contains(&unit), "{:?}", linked);
+        assert!(linked.contains(&map), "{:?}", linked);
+        assert!(!plain.contains("\x1b]8;"), "{:?}", plain);
+
+        // With the escapes gone, the table is the same, column for column.
+        let widths = |text: &str| strip_ansi(text).lines().map(|line| line.chars().count()).collect::<Vec<_>>();
+        assert_eq!(widths(&linked), widths(&plain));
+        assert_eq!(strip_ansi(&linked), strip_ansi(&plain));
+    }
+
+    #[test]
+    fn non_utc_offsets_from_the_api_are_noticed() {
+        let fixture = r#"{"events": [
+            {"desc1": "COMS10016: Imperative Programming", "desc2": "Lecture", "start": "2026-10-19T10:00:00+01:00",
+             "end": "2026-10-19T12:00:00+01:00", "locAdd1": "Queens Building 1.40", "teacherName": "Dr Smith"},
+            {"desc1": "COMS10017: Object-Oriented Programming", "desc2": "Lecture", "start": "2026-10-20T09:00:00Z",
+             "end": "2026-10-20T10:00:00+00:00", "locAdd1": "Fry Building: G.07", "teacherName": "Dr Jones"}
+        ]}"#;
+        let source = Bristol { base_url: BRISTOL_BASE_URL.to_string() };
+        let (mut events, malformed) = source.parse_events(fixture).unwrap();
+        assert!(malformed.is_empty());
+        normalise_timestamps(&mut events);
+        assert_eq!(non_utc_offsets(&events), ["+01:00"]);
+
+        // UTC however it's written, including the fallback formats, doesn't warn.
+        events.remove(0);
+        events.push(Event { start: "2026-10-21 09:00:00".to_string(), end: "2026-10-21 10:00:00".to_string(), ..events[0].clone() });
+        normalise_timestamps(&mut events);
+        assert_eq!(events[1].start, "2026-10-21T09:00:00+00:00");
+        assert!(non_utc_offsets(&events).is_empty(), "{:?}", events);
+    }
+
+    #[test]
+    fn validation_swaps_an_end_before_the_start() {
+        let mut events = [lecture("2026-10-19T12:00:00+01:00", "2026-10-19T10:00:00+01:00")];
+        validate_events(&mut events, Duration::hours(12));
+        assert!(matches!(events[0].issue, Some(EventIssue::Reversed)));
+        assert_eq!((events[0].start.as_str(), events[0].end.as_str()), ("2026-10-19T10:00:00+01:00", "2026-10-19T12:00:00+01:00"));
+    }
+
+    #[test]
+    fn validation_accepts_zero_length_events() {
+        let mut events = [lecture("2026-10-19T10:00:00+01:00", "2026-10-19T10:00:00+01:00")];
+        validate_events(&mut events, Duration::hours(12));
+        assert!(events[0].issue.is_none());
+        assert_eq!(events[0].start, events[0].end);
+    }
+
+    #[test]
+    fn validation_flags_events_over_the_duration_limit() {
+        let mut events = [
+            lecture("2026-10-19T09:00:00+01:00", "2026-10-19T21:00:00+01:00"),
+            lecture("2026-10-19T09:00:00+01:00", "2026-10-19T21:00:01+01:00"),
+        ];
+        validate_events(&mut events, Duration::hours(12));
+        assert!(events[0].issue.is_none(), "exactly at the limit is fine");
+        assert!(matches!(events[1].issue, Some(EventIssue::TooLong)));
+    }
+
+    #[test]
+    fn validation_leaves_unparseable_times_alone() {
+        let mut events = [lecture("2026-10-19T10:00:00+01:00", "half past ten"), lecture("soon", "2026-10-19T10:00:00+01:00")];
+        validate_events(&mut events, Duration::hours(12));
+        assert!(events.iter().all(|event| event.issue.is_none()));
+        assert_eq!(events[0].end, "half past ten");
+        assert_eq!(events[1].start, "soon");
+    }
+
+    #[test]
+    fn a_date_outside_the_fetched_window_is_explained() {
+        display_in_london();
+        let now = DateTime::parse_from_rfc3339("2026-10-19T11:00:00+01:00").unwrap();
+        let report = FetchReport {
+            window_start: Utc.from_utc_datetime(&date(5).and_hms_opt(12, 0, 0).unwrap()),
+            window_end: Utc.from_utc_datetime(&date(25).and_hms_opt(12, 0, 0).unwrap()),
+            ..FetchReport::default()
+        };
+        let render = |target_date: NaiveDate| {
+            let context = ViewContext { target_date, now, is_today: target_date == now.date_naive() };
+            render_timetable(vec![], &context, &ViewOptions::default(), &report)
+        };
+        let range = "outside the fetched range (2026-10-05 to 2026-10-25)";
+        let after = render(date(28));
+        assert!(after.contains(&format!("The requested date (2026-10-28) is {}; pass --window or adjust fetch_days_future.", range)), "{}", after);
+        let before = render(date(2));
+        assert!(before.contains(&format!("The requested date (2026-10-02) is {}; pass --window or adjust fetch_days_past.", range)), "{}", before);
+        // The window's own first and last days are inside it, and simply free.
+        for day in [5, 25] {
+            let out = render(date(day));
+            assert!(!out.contains("outside the fetched range"), "{}", out);
+            assert!(out.contains("No events scheduled for this day."), "{}", out);
+        }
+    }
+
+    #[test]
+    fn asking_for_a_far_day_stretches_the_window_to_reach_it() {
+        display_in_london();
+        let mut config: Config = toml::from_str("[api]\ncookie = \"abc\"\n").unwrap();
+        // The window fetch_events would ask for, as the report would record it.
+        let report = |api: &ApiConfig| {
+            let now = Utc::now();
+            FetchReport {
+                window_start: now - Duration::days(api.fetch_days_past),
+                window_end: now + Duration::days(api.fetch_days_future),
+                ..FetchReport::default()
+            }
+        };
+        let (far_past, far_future) = (display_today() - Duration::days(30), display_today() + Duration::days(200));
+        assert!(!report(&config.api).covers(far_past) && !report(&config.api).covers(far_future));
+
+        assert_eq!(target_date(&mut config, "-30", false).unwrap(), far_past);
+        assert_eq!(target_date(&mut config, "200", false).unwrap(), far_future);
+        assert!(report(&config.api).covers(far_past), "{:?}", config.api);
+        assert!(report(&config.api).covers(far_future), "{:?}", config.api);
+
+        // With --week, the whole of that week.
+        let week = target_date(&mut config, "300", true).unwrap();
+        let monday = week_start(week);
+        assert!((0..7).all(|day| report(&config.api).covers(monday + Duration::days(day))), "{:?}", config.api);
+        assert!(target_date(&mut config, "soon", false).is_err());
+    }
+
+    #[test]
+    fn legacy_args_rewrite_to_subcommands() {
+        let cases: &[(&str, &str)] = &[
+            ("bstt", "bstt day"),
+            ("bstt +1", "bstt day +1"),
+            ("bstt -1", "bstt day -1"),
+            ("bstt +1 --week", "bstt week +1"),
+            ("bstt --mini --polybar", "bstt mini --polybar"),
+            ("bstt --profile x --serve --port 8080", "bstt serve --profile x --port 8080"),
+            ("bstt --config=- week", "bstt --config=- week"),
+        ];
+        for (legacy, expected) in cases {
+            let args = legacy_args(legacy.split(' ').map(OsString::from).collect());
+            let rewritten = args.iter().map(|arg| arg.to_str().unwrap()).collect::<Vec<_>>().join(" ");
+            assert_eq!(&rewritten, expected, "{}", legacy);
+            if let Err(e) = Cli::try_parse_from(&args) {
+                panic!("{} -> {} doesn't parse: {}", legacy, rewritten, e);
+            }
+        }
+    }
+
+    #[test]
+    fn to_display_follows_the_clocks_going_back() {
+        display_in_london();
+        // The clocks go back at 01:00 UTC on Sunday 25 October 2026, so 01:30 happens twice.
+        let cases = [
+            ((0, 30), "2026-10-25T01:30:00+01:00"),
+            ((0, 59), "2026-10-25T01:59:00+01:00"),
+            ((1, 0), "2026-10-25T01:00:00+00:00"),
+            ((1, 30), "2026-10-25T01:30:00+00:00"),
+            ((12, 0), "2026-10-25T12:00:00+00:00"),
+        ];
+        for ((hour, minute), expected) in cases {
+            let utc = Utc.from_utc_datetime(&date(25).and_hms_opt(hour, minute, 0).unwrap());
+            assert_eq!(to_display(&utc).to_rfc3339(), expected, "{}", utc);
+        }
+        let before = Utc.from_utc_datetime(&date(24).and_hms_opt(12, 0, 0).unwrap());
+        assert_eq!(to_display(&before).to_rfc3339(), "2026-10-24T13:00:00+01:00");
+    }
+
+    #[test]
+    fn teaching_day_skips_empty_weekends_both_ways() {
+        // Friday 16, Saturday 17, Sunday 18 and Monday 19 October 2026.
+        let cases = [
+            // (from, offset, Saturday has an event, expected)
+            (16, 1, false, 19),
+            (19, -1, false, 16),
+            (16, 1, true, 17),
+            (19, -1, true, 17),
+            (16, 2, true, 19),
+            (19, -2, true, 16),
+            (16, 2, false, 20),
+            (19, -2, false, 15),
+            (17, 0, false, 17),
+        ];
+        for (from, offset, saturday_event, expected) in cases {
+            let has_events = |day: NaiveDate| saturday_event && day == date(17);
+            assert_eq!(teaching_day(date(from), offset, 5, has_events), date(expected), "{} {:+} (Saturday event: {})", from, offset, saturday_event);
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// tests/cron.rs
+//
+// Runs bstt as cron would: no HOME, TZ or locale, and neither stdout nor stderr a terminal.
+// The config comes from standard input, so nothing here needs the network or a config file.
+
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+fn run_scrubbed(config: &str, args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bstt"))
+        .env_clear()
+        .arg("--config")
+        .arg("-")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("bstt should start");
+    child.stdin.take().unwrap().write_all(config.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn assert_plain(text: &str) {
+    assert!(!text.contains('\x1b'), "colour codes in non-terminal output: {:?}", text);
+}
+
+#[test]
+fn config_show_works_without_home_tz_or_locale() {
+    let output = run_scrubbed("[api]\ncookie = \"abc\"\n[display]\ntimezone = \"Europe/London\"\n", &["config", "show", "--origins"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(stdout.contains("timezone = \"Europe/London\"  # <stdin>"), "{}", stdout);
+    assert!(!stderr.contains("Config file not found"), "{}", stderr);
+    assert_plain(&stdout);
+    assert_plain(&stderr);
+}
+
+#[test]
+fn warnings_go_to_the_log_file_when_stderr_is_not_a_terminal() {
+    let log = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cron-warnings.log");
+    let _ = std::fs::remove_file(&log);
+    // Looking a century ahead runs past the bundled bank holidays, which warns.
+    let config = format!("[api]\ncookie = \"abc\"\nfetch_days_future = 36500\n[log]\nfile = {:?}\n", log.display().to_string());
+    let output = run_scrubbed(&config, &["bank-holidays"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(output.status.success(), "stderr: {}", stderr);
+    assert!(stderr.is_empty(), "expected the warning in the log file, not stderr: {}", stderr);
+    let logged = std::fs::read_to_string(&log).expect("the log file should have been written");
+    assert!(logged.contains("Warning: The bank holiday list ends before then"), "{}", logged);
+    assert_plain(&logged);
+}
+
+#[test]
+fn an_unknown_timezone_is_reported_plainly() {
+    let output = run_scrubbed("[api]\ncookie = \"abc\"\n[display]\ntimezone = \"Mars/Olympus\"\n", &["config", "show"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr.contains("Unknown timezone \"Mars/Olympus\""), "{}", stderr);
+    assert_plain(&stderr);
+}
+
+#[test]
+fn a_setting_under_both_its_old_and_new_names_is_an_error() {
+    let output = run_scrubbed("[api]\ncookie = \"abc\"\nwindow_before_days = 7\nfetch_days_past = 14\n", &["config", "show"]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(!output.status.success());
+    assert!(stderr.contains("[api] sets both fetch_days_past and window_before_days"), "{}", stderr);
+    assert!(stderr.contains("(in <stdin>)"), "{}", stderr);
+    assert_plain(&stderr);
+
+    // Either name on its own is fine, as is the old name in the file and the new one from a flag.
+    for config in ["[api]\ncookie = \"abc\"\nwindow_before_days = 7\n", "[api]\ncookie = \"abc\"\nfetch_days_past = 7\n"] {
+        let output = run_scrubbed(config, &["config", "show"]);
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(stdout.contains("fetch_days_past = 7"), "{}", stdout);
+    }
+    let output = run_scrubbed("[api]\ncookie = \"abc\"\nwindow_before_days = 7\n", &["--window", "3", "config", "show"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("fetch_days_past = 3"), "{}", stdout);
+}